@@ -1,57 +1,94 @@
+//Anchor 0.24 errors are large, boxing them would touch every handler
+#![allow(clippy::result_large_err)]
+
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken, 
 
-    token::{ Token, Transfer, TokenAccount, Mint}
+    token::{ Token, TokenAccount, Mint, MintTo, Burn, SetAuthority, CloseAccount}
 };
 use anchor_lang::require;
 use anchor_lang::prelude::Clock;
+use anchor_lang::AccountsClose;
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
+//History entries can be closed once they are 30 days old
+const ENTRY_RETENTION_PERIOD: i64 = 2592000;
+
+//Lots added to LockedPool each time it runs out of space
+const LOT_GROWTH: usize = 10;
+
+//Lots are locked for 15 days after their start time
+const LOCKING_PERIOD: i64 = 1296000;
+
+//Longest lock a funder can put on a gifted lot, one year
+const MAX_GIFT_LOCK: i64 = 31536000;
+
+//Fixed capacity of the zero-copy LotPosition
+const POSITION_LOTS: usize = 128;
+
+//Lot flags
+const LOT_ACTIVE: u8 = 1;
+
+//Where early withdraw penalties go
+const PENALTY_TREASURY: u8 = 0;
+const PENALTY_INSURANCE: u8 = 1;
+const PENALTY_REDISTRIBUTE: u8 = 2;
+
+//Fixed point scale of StakePool.reward_per_share
+const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
+//Where distributed rewards came from
+const REWARD_SOURCE_PENALTY: u8 = 0;
+const REWARD_SOURCE_DEPOSIT: u8 = 1;
+
+//Accounting unit of the basket pool, every allowlisted stablecoin is normalized to it
+const BASKET_DECIMALS: u8 = 6;
+
 #[program]
 pub mod staking_contract {
 
     use super::*;
 
+    //Deprecated: kept for existing clients, use stake or request_withdraw
     pub fn perform_action(
         ctx: Context<PerformAction>,
         action_amount: u64, 
         action_token: Pubkey,
         stake_action: bool,
-        count: u8
+        _count: u8 //Ignored, the entry index is owned by the program
     ) -> Result<()> {
        let clock = Clock::get()?;
        let current_time = clock.unix_timestamp;
 
-       let current_interest = ctx.accounts.token_interest.interest.clone() as u64;
-       let current_user = ctx.accounts.staker.clone();
-       let token_program = ctx.accounts.token_mint.clone();
-              
-       let staking_pool = &mut ctx.accounts.current_staking_pool;
-       let pool_action = &mut ctx.accounts.pool_action;
-       let locked_pool_action = &mut ctx.accounts.lock_pool_action;
-        let withdraw_pool_action = &mut ctx.accounts.withdraw_pool_action;
+       let current_interest = ctx.accounts.token_interest.interest;
+       let current_user = ctx.accounts.staker.key();
+       let token_mint_key = ctx.accounts.token_mint.key();
 
-       let pool_action_entry = &mut ctx.accounts.pool_entry;
-       let pool_count = &mut ctx.accounts.pool_count;
+       require!(token_mint_key == action_token, ErrorCode::InvalidToken);  
 
-       require!(token_program.key() == action_token, ErrorCode::InvalidToken);  
-        
-        //Update the Unlocked token
-        let locked_amounts =  locked_pool_action.locked_amount.clone();
-        let locked_start_times =  locked_pool_action.locked_start_time.clone();
-        let total_length = locked_amounts.len();
+       //Stakes book what reached the vault
+       let mut booked_amount = action_amount;
 
        //Stake Action
        if stake_action {
 
-        // Update Pool Action
-        pool_action.token_amount += action_amount;
+        //Grow the lot account when every lot is in use
+        let lots = ctx.accounts.lock_pool_action.lots_after_stake();
+        realloc_locked_pool(
+            &ctx.accounts.lock_pool_action.to_account_info(),
+            &ctx.accounts.staker.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            lots
+        )?;
+
+        let vault_before = ctx.accounts.staking_vault_associated_address.amount;
 
         //Transfer Funds
-        let transfer_instruction = Transfer{
+        let transfer_instruction = TransferChecked{
             from: ctx.accounts.staker_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
             to: ctx.accounts.staking_vault_associated_address.to_account_info(),
             authority: ctx.accounts.staker.to_account_info(),
         };
@@ -59,137 +96,521 @@ pub mod staking_contract {
             ctx.accounts.token_program.to_account_info(),
             transfer_instruction,
         );
-        anchor_spl::token::transfer(cpi_ctx, action_amount)?;
+        transfer_checked(cpi_ctx, action_amount, ctx.accounts.current_staking_pool.decimals)?;
 
-        //Update Staking Pool
-        staking_pool.token_amount += action_amount;
-        
-        let mut pool_updated = false;
-
-        for n in 0..total_length {
-            if locked_amounts[n] == locked_start_times[n] as u64 && locked_start_times[n] == 0{
-                //Update lock pool action
-                locked_pool_action.locked_amount[n] = action_amount;
-                locked_pool_action.locked_start_time[n] = current_time;
-                pool_updated = true;
-                break;
-            }
-        }
-        if !pool_updated{
-            locked_pool_action.locked_amount.push(action_amount);
-            locked_pool_action.locked_start_time.push(current_time);
-        }
+        //Credit what reached the vault, not the nominal amount
+        ctx.accounts.staking_vault_associated_address.reload()?;
+        booked_amount = ctx.accounts.current_staking_pool.received_amount(action_amount, vault_before, ctx.accounts.staking_vault_associated_address.amount)?;
+
+        book_stake(
+            &mut ctx.accounts.current_staking_pool,
+            &mut ctx.accounts.pool_action,
+            &mut ctx.accounts.lock_pool_action,
+            booked_amount,
+            0,
+            current_interest,
+            current_time
+        )?;
 
-        //Update Pool Action Entry 
-        pool_action_entry.confirmed = true;
+        emit!(Staked {
+            staker: current_user,
+            token_mint: token_mint_key,
+            amount: booked_amount,
+            interest_rate: current_interest,
+            time_stamp: current_time,
+        });
 
        }
 
        //Withdraw Action/ Unstake Action
        else {
 
-        //Handle Withdraw Request
-        // Queue their withdraw to next sunday
+        //Older accounts need room for the fields of every lot
+        let lots = ctx.accounts.lock_pool_action.locked_amount.len();
+        realloc_locked_pool(
+            &ctx.accounts.lock_pool_action.to_account_info(),
+            &ctx.accounts.staker.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            lots
+        )?;
+
+        let realized_interest = book_withdraw_request(
+            &mut ctx.accounts.current_staking_pool,
+            &mut ctx.accounts.pool_action,
+            &mut ctx.accounts.lock_pool_action,
+            &mut ctx.accounts.withdraw_pool_action,
+            action_amount,
+            current_interest,
+            current_time
+        )?;
+
+        //Settled interest is paid out first
+        let interest_amount = realized_interest.min(action_amount);
+        emit!(WithdrawRequested {
+            staker: current_user,
+            token_mint: token_mint_key,
+            amount: action_amount,
+            principal_amount: action_amount - interest_amount,
+            interest_amount,
+            time_stamp: current_time,
+        });
+        
+       }
+
+        record_entry(
+            &mut ctx.accounts.pool_entry,
+            &mut ctx.accounts.pool_count,
+            current_user,
+            booked_amount,
+            stake_action,
+            current_time
+        );
+
+       Ok(())
+    }
+
+    pub fn stake(
+        ctx: Context<Stake>,
+        action_amount: u64
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+
+        //Grow the lot account when every lot is in use
+        let lots = ctx.accounts.lock_pool_action.lots_after_stake();
+        realloc_locked_pool(
+            &ctx.accounts.lock_pool_action.to_account_info(),
+            &ctx.accounts.staker.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            lots
+        )?;
+
+        let vault_before = ctx.accounts.staking_vault_associated_address.amount;
+
+        //Transfer Funds
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.staker_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            authority: ctx.accounts.staker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+        transfer_checked(cpi_ctx, action_amount, ctx.accounts.current_staking_pool.decimals)?;
+
+        //Credit what reached the vault, not the nominal amount
+        ctx.accounts.staking_vault_associated_address.reload()?;
+        let action_amount = ctx.accounts.current_staking_pool.received_amount(action_amount, vault_before, ctx.accounts.staking_vault_associated_address.amount)?;
+
+        book_stake(
+            &mut ctx.accounts.current_staking_pool,
+            &mut ctx.accounts.pool_action,
+            &mut ctx.accounts.lock_pool_action,
+            action_amount,
+            0,
+            current_interest,
+            current_time
+        )?;
+
+        record_entry(
+            &mut ctx.accounts.pool_entry,
+            &mut ctx.accounts.pool_count,
+            ctx.accounts.staker.key(),
+            action_amount,
+            true,
+            current_time
+        );
+
+        emit!(Staked {
+            staker: ctx.accounts.staker.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: action_amount,
+            interest_rate: current_interest,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Stake from the funder's tokens into a position owned by `beneficiary`
+    //A `lock_until` in the future blocks withdraws of the gifted lot only, at most a year ahead
+    pub fn stake_for(
+        ctx: Context<StakeFor>,
+        action_amount: u64,
+        lock_until: i64
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+
+        require!(lock_until <= current_time + MAX_GIFT_LOCK, ErrorCode::InvalidLockDate);
+        //A locked gift holds the beneficiary's cap and exits, so they must co-sign it
+        require!(lock_until <= current_time || ctx.accounts.beneficiary.is_signer, ErrorCode::GiftLockNotAccepted);
+
+        //Grow the lot account when every lot is in use, paid by the funder
+        let lots = ctx.accounts.lock_pool_action.lots_after_stake();
+        realloc_locked_pool(
+            &ctx.accounts.lock_pool_action.to_account_info(),
+            &ctx.accounts.funder.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            lots
+        )?;
+
+        let vault_before = ctx.accounts.staking_vault_associated_address.amount;
+
+        //Transfer Funds
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.funder_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            authority: ctx.accounts.funder.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+        transfer_checked(cpi_ctx, action_amount, ctx.accounts.current_staking_pool.decimals)?;
+
+        //Credit what reached the vault, not the nominal amount
+        ctx.accounts.staking_vault_associated_address.reload()?;
+        let action_amount = ctx.accounts.current_staking_pool.received_amount(action_amount, vault_before, ctx.accounts.staking_vault_associated_address.amount)?;
+
+        book_stake(
+            &mut ctx.accounts.current_staking_pool,
+            &mut ctx.accounts.pool_action,
+            &mut ctx.accounts.lock_pool_action,
+            action_amount,
+            lock_until,
+            current_interest,
+            current_time
+        )?;
+
+        record_entry(
+            &mut ctx.accounts.pool_entry,
+            &mut ctx.accounts.pool_count,
+            ctx.accounts.beneficiary.key(),
+            action_amount,
+            true,
+            current_time
+        );
+
+        emit!(StakedFor {
+            funder: ctx.accounts.funder.key(),
+            beneficiary: ctx.accounts.beneficiary.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: action_amount,
+            locked_until: lock_until,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Stake lamports into a native SOL pool, wrapped into the wSOL vault
+    pub fn stake_sol(
+        ctx: Context<StakeSol>,
+        action_amount: u64
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+
+        //Grow the lot account when every lot is in use
+        let lots = ctx.accounts.lock_pool_action.lots_after_stake();
+        realloc_locked_pool(
+            &ctx.accounts.lock_pool_action.to_account_info(),
+            &ctx.accounts.staker.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            lots
+        )?;
+
+        //Wrap Lamports
+        let transfer_instruction = anchor_lang::system_program::Transfer{
+            from: ctx.accounts.staker.to_account_info(),
+            to: ctx.accounts.staking_vault_associated_address.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            transfer_instruction,
+        );
+        anchor_lang::system_program::transfer(cpi_ctx, action_amount)?;
+
+        let sync_instruction = anchor_spl::token::spl_token::instruction::sync_native(
+            &anchor_spl::token::ID,
+            &ctx.accounts.staking_vault_associated_address.key()
+        )?;
+        anchor_lang::solana_program::program::invoke(
+            &sync_instruction,
+            &[
+                ctx.accounts.staking_vault_associated_address.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ]
+        )?;
+
+        book_stake(
+            &mut ctx.accounts.current_staking_pool,
+            &mut ctx.accounts.pool_action,
+            &mut ctx.accounts.lock_pool_action,
+            action_amount,
+            0,
+            current_interest,
+            current_time
+        )?;
+
+        record_entry(
+            &mut ctx.accounts.pool_entry,
+            &mut ctx.accounts.pool_count,
+            ctx.accounts.staker.key(),
+            action_amount,
+            true,
+            current_time
+        );
+
+        emit!(Staked {
+            staker: ctx.accounts.staker.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: action_amount,
+            interest_rate: current_interest,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn request_withdraw(
+        ctx: Context<RequestWithdraw>,
+        action_amount: u64
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+
+        //Older accounts need room for the fields of every lot
+        let lots = ctx.accounts.lock_pool_action.locked_amount.len();
+        realloc_locked_pool(
+            &ctx.accounts.lock_pool_action.to_account_info(),
+            &ctx.accounts.staker.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            lots
+        )?;
+
+        let realized_interest = book_withdraw_request(
+            &mut ctx.accounts.current_staking_pool,
+            &mut ctx.accounts.pool_action,
+            &mut ctx.accounts.lock_pool_action,
+            &mut ctx.accounts.withdraw_pool_action,
+            action_amount,
+            current_interest,
+            current_time
+        )?;
+
+        record_entry(
+            &mut ctx.accounts.pool_entry,
+            &mut ctx.accounts.pool_count,
+            ctx.accounts.staker.key(),
+            action_amount,
+            false,
+            current_time
+        );
+
+        //Settled interest is paid out first
+        let interest_amount = realized_interest.min(action_amount);
+        emit!(WithdrawRequested {
+            staker: ctx.accounts.staker.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: action_amount,
+            principal_amount: action_amount - interest_amount,
+            interest_amount,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+   
+
+    //Stake without lots and receive transferable receipt tokens minted by the pool
+    pub fn stake_liquid(
+        ctx: Context<StakeLiquid>,
+        action_amount: u64
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+        let token_mint_key = ctx.accounts.token_mint.key();
+        let current_staking_pool_account = ctx.accounts.current_staking_pool.to_account_info();
+
+        let vault_before = ctx.accounts.staking_vault_associated_address.amount;
+
+        //Transfer Funds
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.staker_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            authority: ctx.accounts.staker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+        transfer_checked(cpi_ctx, action_amount, ctx.accounts.current_staking_pool.decimals)?;
+
+        //Credit what reached the vault, not the nominal amount
+        ctx.accounts.staking_vault_associated_address.reload()?;
+        let action_amount = ctx.accounts.current_staking_pool.received_amount(action_amount, vault_before, ctx.accounts.staking_vault_associated_address.amount)?;
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.accrue_interest(current_interest, current_time);
+        require!(!staking_pool.circuit_breaker, ErrorCode::PoolUndercollateralized);
+        //Receipts are transferable, so the staker's liquid stake is the value of the receipts they hold now
+        let receipt_value = staking_pool.receipt_value(ctx.accounts.staker_receipt_address.amount, ctx.accounts.receipt_mint.supply);
+        staking_pool.check_stake_limits(action_amount, ctx.accounts.pool_action.staked() + receipt_value)?;
+
+        //Price receipts before adding the new stake
+        let receipt_amount = staking_pool.receipts_for(action_amount, ctx.accounts.receipt_mint.supply);
+        staking_pool.token_amount += action_amount;
+        staking_pool.total_staked += action_amount;
+        staking_pool.liquid_staked += action_amount;
+
+        //Mint Receipts
+        let bump_seed_staking_pool = ctx.bumps.get("current_staking_pool").unwrap().to_le_bytes();
+        let staking_pool_signer_seeds: &[&[_]] = &[
+            b"stake_pool".as_ref(),
+            token_mint_key.as_ref(),
+            &bump_seed_staking_pool
+        ];
+
+        let mint_instruction = MintTo{
+            mint: ctx.accounts.receipt_mint.to_account_info(),
+            to: ctx.accounts.staker_receipt_address.to_account_info(),
+            authority: current_staking_pool_account,
+        };
+
+        let signer = &[staking_pool_signer_seeds];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            mint_instruction,
+            signer,
+        );
+        anchor_spl::token::mint_to(cpi_ctx, receipt_amount)?;
+
+        record_entry(
+            &mut ctx.accounts.pool_entry,
+            &mut ctx.accounts.pool_count,
+            ctx.accounts.staker.key(),
+            action_amount,
+            true,
+            current_time
+        );
+
+        emit!(LiquidStaked {
+            staker: ctx.accounts.staker.key(),
+            token_mint: token_mint_key,
+            amount: action_amount,
+            receipt_amount,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Burn receipt tokens and queue their value, principal and interest, for claim_withdraw
+    pub fn request_withdraw_liquid(
+        ctx: Context<RequestWithdrawLiquid>,
+        receipt_amount: u64
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+
         // Withdraw request can only be made from Monday to Friday 
         let day_of_week = (current_time/86400 + 4)%7;
         require!(day_of_week != 1, ErrorCode::InvalidWithdrawDay);
 
-        //withdraw action amount update
-        let withdraw_action_amount = withdraw_pool_action.requested_amount + action_amount;
-
-        for n in 0..total_length {
-            ////TODO::Uncomment on production
-            // if locked_start_times[n] + 1296000 < current_time {
-                let interest_amount = (current_time -  locked_pool_action.locked_start_time[n]) as u64 * (((current_interest/31536000)*locked_pool_action.locked_amount[n] as u64)/100) as u64;
-                withdraw_pool_action.requested_amount += locked_pool_action.locked_amount[n] + interest_amount;
-                
-                if withdraw_pool_action.requested_amount > withdraw_action_amount{
-                    locked_pool_action.locked_amount[n] = withdraw_pool_action.requested_amount - withdraw_action_amount;
-                    withdraw_pool_action.requested_amount = withdraw_action_amount;
-                }
-                else{
-                    locked_pool_action.locked_start_time[n] = 0;
-                    locked_pool_action.locked_amount[n] = 0;
-                }
-            // }
-        }
-        //Check Unlocked Amount i.e. exceeded 15 days Locking
-        require!(withdraw_pool_action.requested_amount >= action_amount, ErrorCode::LockingPeriod);
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.accrue_interest(current_interest, current_time);
 
-       
+        let action_amount = staking_pool.receipt_value(receipt_amount, ctx.accounts.receipt_mint.supply);
         require!(staking_pool.token_amount >= action_amount, ErrorCode::NotEnoughToken);
-        pool_action.token_amount -= action_amount;
 
-        //Update Withdraw Pool Time
-        withdraw_pool_action.requested_time = current_time;
-        
-        //Update Staking Pool
-        // staking_pool.token_amount -= action_amount;
+        //Burn Receipts
+        let burn_instruction = Burn{
+            mint: ctx.accounts.receipt_mint.to_account_info(),
+            from: ctx.accounts.staker_receipt_address.to_account_info(),
+            authority: ctx.accounts.staker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            burn_instruction,
+        );
+        anchor_spl::token::burn(cpi_ctx, receipt_amount)?;
 
-        //Update Pool Action Entry 
-        pool_action_entry.confirmed = false;
-        
-       }
+        staking_pool.liquid_staked -= action_amount;
+        staking_pool.total_staked = staking_pool.total_staked.saturating_sub(action_amount);
+        staking_pool.pending_withdraw += action_amount;
 
-        // Update Action Pool 
-        if pool_action.start_time == 0{
-            pool_action.start_time = current_time; //Set the start time for the first time 
-            pool_action.update_time = current_time; //Set the update time to current time
-        
-        }
-        else{
-            pool_action.update_time = current_time; //Set the update time to current time
-        }
+        let withdraw_pool_action = &mut ctx.accounts.withdraw_pool_action;
+        withdraw_pool_action.requested_amount += action_amount;
+        withdraw_pool_action.requested_time = current_time;
 
-        // Update Pool Entry
-        pool_action_entry.stake_action = stake_action;
-        pool_action_entry.staker = current_user.key();
-        pool_action_entry.token_amount = action_amount;
-        pool_action_entry.time_stamp = current_time;
-        
+        record_entry(
+            &mut ctx.accounts.pool_entry,
+            &mut ctx.accounts.pool_count,
+            ctx.accounts.staker.key(),
+            action_amount,
+            false,
+            current_time
+        );
 
-        
-        pool_count.count = count;
+        emit!(LiquidWithdrawRequested {
+            staker: ctx.accounts.staker.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            receipt_amount,
+            amount: action_amount,
+            time_stamp: current_time,
+        });
 
-       Ok(())
+        Ok(())
     }
-   
 
     pub fn claim_withdraw(
         ctx: Context<PerformWithdraw>,
-        claim_amount: u64, 
-        count: u8
+        claim_amount: u64,
+        _count: u8 //Ignored, the entry index is owned by the program
     ) -> Result<()>{
         let clock = Clock::get()?;
         let current_time = clock.unix_timestamp;
 
         let current_user = ctx.accounts.staker.clone();
-        let token_program = ctx.accounts.token_mint.clone();
-
-        let withdraw_pool_action = &mut ctx.accounts.withdraw_pool_action;
-        let pool_action_entry = &mut ctx.accounts.pool_entry;
-        let pool_count = &mut ctx.accounts.pool_count;
-
 
         let token_mint_key = ctx.accounts.token_mint.clone().key();
         let current_staking_pool_account = ctx.accounts.current_staking_pool.clone().to_account_info();
 
         require!(ctx.accounts.current_staking_pool.token_amount >= claim_amount, ErrorCode::ExceedPoolAmount );
 
-        let day_of_week = (current_time/86400 + 4)%7;
-        //TODO::Uncomment on production
-        // require!(day_of_week == 1, ErrorCode::InvalidWithdrawDay);
+        //Pay to the registered payout address, or to the staker's own account if none
+        let payout_config = &mut ctx.accounts.payout_config;
+        payout_config.apply_pending(current_time);
+        if payout_config.payout_address == Pubkey::default() {
+            require!(ctx.accounts.payout_owner.key() == current_user.key(), ErrorCode::InvalidPayoutAddress);
+        }
+        else {
+            require!(ctx.accounts.staker_associated_address.key() == payout_config.payout_address, ErrorCode::InvalidPayoutAddress);
+        }
 
         //Transfer Funds
         let bump_seed_staking_pool = ctx.bumps.get("current_staking_pool").unwrap().to_le_bytes();
         let staking_pool_signer_seeds: &[&[_]] = &[
             b"stake_pool".as_ref(),
-            &token_mint_key.as_ref(),
+            token_mint_key.as_ref(),
             &bump_seed_staking_pool
         ];
 
-        let transfer_instruction = Transfer{
+        let transfer_instruction = TransferChecked{
             from: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
             to: ctx.accounts.staker_associated_address.to_account_info(),
             authority: current_staking_pool_account.clone(),
         };
@@ -200,21 +621,114 @@ pub mod staking_contract {
             transfer_instruction,
             signer,
         );
-        anchor_spl::token::transfer(cpi_ctx, claim_amount)?;
+        transfer_checked(cpi_ctx, claim_amount, ctx.accounts.current_staking_pool.decimals)?;
         
+        let withdraw_pool_action = &mut ctx.accounts.withdraw_pool_action;
         withdraw_pool_action.requested_amount -= claim_amount;
 
-        // Update Pool Entry
-        pool_action_entry.stake_action = false;
-        pool_action_entry.staker = current_user.key();
-        pool_action_entry.token_amount = claim_amount;
-        pool_action_entry.time_stamp = current_time;
-        pool_action_entry.confirmed = true;
+        //Reduce Token Amount on Staking Pool
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.token_amount -= claim_amount;
+        staking_pool.book_claim(claim_amount);
+
+        record_entry(
+            &mut ctx.accounts.pool_entry,
+            &mut ctx.accounts.pool_count,
+            current_user.key(),
+            claim_amount,
+            false,
+            current_time
+        );
+        ctx.accounts.pool_entry.confirmed = true;
 
-        pool_count.count = count;
+        emit!(WithdrawClaimed {
+            staker: current_user.key(),
+            token_mint: token_mint_key,
+            amount: claim_amount,
+            remaining_requested: ctx.accounts.withdraw_pool_action.requested_amount,
+            time_stamp: current_time,
+        });
 
-        //Reduce Token Amount on Staking Pool
-        ctx.accounts.current_staking_pool.token_amount -= claim_amount;
+        Ok(())
+    }
+
+    //Claim from a native SOL pool, the wSOL is unwrapped and paid out as lamports
+    pub fn claim_withdraw_sol(
+        ctx: Context<ClaimWithdrawSol>,
+        claim_amount: u64
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let token_mint_key = ctx.accounts.token_mint.key();
+        let current_staking_pool_account = ctx.accounts.current_staking_pool.to_account_info();
+
+        require!(ctx.accounts.current_staking_pool.token_amount >= claim_amount, ErrorCode::ExceedPoolAmount);
+        require!(ctx.accounts.withdraw_pool_action.requested_amount >= claim_amount, ErrorCode::NotEnoughToken);
+
+        //Lamports only go to the staker, a registered payout address is claimed through claim_withdraw
+        let payout_config = &mut ctx.accounts.payout_config;
+        payout_config.apply_pending(current_time);
+        require!(payout_config.payout_address == Pubkey::default(), ErrorCode::InvalidPayoutAddress);
+
+        let bump_seed_staking_pool = ctx.bumps.get("current_staking_pool").unwrap().to_le_bytes();
+        let staking_pool_signer_seeds: &[&[_]] = &[
+            b"stake_pool".as_ref(),
+            token_mint_key.as_ref(),
+            &bump_seed_staking_pool
+        ];
+        let signer = &[staking_pool_signer_seeds];
+
+        //Move the claim out of the vault into the temporary account
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.unwrap_associated_address.to_account_info(),
+            authority: current_staking_pool_account.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+            signer,
+        );
+        transfer_checked(cpi_ctx, claim_amount, ctx.accounts.current_staking_pool.decimals)?;
+
+        //Closing it unwraps, the staker gets the claim and the rent back
+        let close_instruction = CloseAccount{
+            account: ctx.accounts.unwrap_associated_address.to_account_info(),
+            destination: ctx.accounts.staker.to_account_info(),
+            authority: current_staking_pool_account,
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            close_instruction,
+            signer,
+        );
+        anchor_spl::token::close_account(cpi_ctx)?;
+
+        let withdraw_pool_action = &mut ctx.accounts.withdraw_pool_action;
+        withdraw_pool_action.requested_amount -= claim_amount;
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.token_amount -= claim_amount;
+        staking_pool.pending_withdraw = staking_pool.pending_withdraw.saturating_sub(claim_amount);
+
+        record_entry(
+            &mut ctx.accounts.pool_entry,
+            &mut ctx.accounts.pool_count,
+            ctx.accounts.staker.key(),
+            claim_amount,
+            false,
+            current_time
+        );
+        ctx.accounts.pool_entry.confirmed = true;
+
+        emit!(WithdrawClaimed {
+            staker: ctx.accounts.staker.key(),
+            token_mint: token_mint_key,
+            amount: claim_amount,
+            remaining_requested: ctx.accounts.withdraw_pool_action.requested_amount,
+            time_stamp: current_time,
+        });
 
         Ok(())
     }
@@ -227,6 +741,12 @@ pub mod staking_contract {
         if current_admin == Pubkey::new(&[0; 32]) || ctx.accounts.owner.key() == current_admin{
             let updated_config = &mut ctx.accounts.admin_config;
             updated_config.admin = new_admin;   
+
+            emit!(AdminChanged {
+                old_admin: current_admin,
+                new_admin,
+                time_stamp: Clock::get()?.unix_timestamp,
+            });
         }
         else{
            return err!(ErrorCode::InvalidAdmin);
@@ -240,10 +760,27 @@ pub mod staking_contract {
         new_interest: u8
     ) -> Result<()> {
         require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
-        
+
+        let clock = Clock::get()?;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
         let interest_rate = &mut ctx.accounts.token_interest;
+
+        //Rate increases are blocked while the pool is under-covered
+        require!(!staking_pool.circuit_breaker || new_interest <= interest_rate.interest, ErrorCode::PoolUndercollateralized);
+
+        //Settle accrual at the old rate before switching
+        staking_pool.accrue_interest(interest_rate.interest, clock.unix_timestamp);
+        let old_interest = interest_rate.interest;
         interest_rate.interest = new_interest;
 
+        emit!(InterestRateUpdated {
+            admin: ctx.accounts.admin.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            old_interest,
+            new_interest,
+            time_stamp: clock.unix_timestamp,
+        });
+
         Ok(())
     }
     
@@ -253,22 +790,30 @@ pub mod staking_contract {
         withdraw_amount: u64
     ) -> Result<()>{
 
+        require!(ctx.accounts.owner.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
         let token_mint_key = ctx.accounts.token_mint.clone().key();
         let current_staking_pool_account = ctx.accounts.current_staking_pool.clone().to_account_info();
        let staking_pool = &mut ctx.accounts.current_staking_pool;
 
         require!(staking_pool.token_amount >= withdraw_amount, ErrorCode::ExceedPoolAmount );
 
+        //Only tokens nobody is owed can be rescued, the interest budget stays as well
+        staking_pool.accrue_interest(ctx.accounts.token_interest.interest, Clock::get()?.unix_timestamp);
+        let vault_amount = ctx.accounts.staking_vault_associated_address.amount;
+        require!(withdraw_amount <= staking_pool.unallocated_amount(vault_amount), ErrorCode::PoolUndercollateralized);
+
          //Transfer Funds
         let bump_seed_staking_pool = ctx.bumps.get("current_staking_pool").unwrap().to_le_bytes();
         let staking_pool_signer_seeds: &[&[_]] = &[
             b"stake_pool".as_ref(),
-            &token_mint_key.as_ref(),
+            token_mint_key.as_ref(),
             &bump_seed_staking_pool
         ];
 
-        let transfer_instruction = Transfer{
+        let transfer_instruction = TransferChecked{
             from: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
             to: ctx.accounts.admin_associated_address.to_account_info(),
             authority: current_staking_pool_account.clone(),
         };
@@ -279,8 +824,16 @@ pub mod staking_contract {
             transfer_instruction,
             signer,
         );
-        anchor_spl::token::transfer(cpi_ctx, withdraw_amount)?;
+        transfer_checked(cpi_ctx, withdraw_amount, staking_pool.decimals)?;
         staking_pool.token_amount -= withdraw_amount;
+        staking_pool.update_coverage(vault_amount - withdraw_amount);
+
+        emit!(TokensRescued {
+            admin: ctx.accounts.owner.key(),
+            token_mint: token_mint_key,
+            amount: withdraw_amount,
+            time_stamp: Clock::get()?.unix_timestamp,
+        });
 
         Ok(())
     }
@@ -296,8 +849,9 @@ pub mod staking_contract {
        let staking_pool = &mut ctx.accounts.current_staking_pool;
 
         //Transfer Funds
-        let transfer_instruction = Transfer{
+        let transfer_instruction = TransferChecked{
             from: ctx.accounts.admin_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
             to: ctx.accounts.staking_vault_associated_address.to_account_info(),
             authority: ctx.accounts.owner.to_account_info(),
         };
@@ -305,37 +859,3571 @@ pub mod staking_contract {
             ctx.accounts.token_program.to_account_info(),
             transfer_instruction,
         );
-        anchor_spl::token::transfer(cpi_ctx, deposit_amount)?;
+        transfer_checked(cpi_ctx, deposit_amount, staking_pool.decimals)?;
 
         staking_pool.token_amount += deposit_amount;
 
+        //Re-evaluate coverage, a sufficient deposit lifts the circuit breaker
+        let clock = Clock::get()?;
+        ctx.accounts.staking_vault_associated_address.reload()?;
+        staking_pool.accrue_interest(ctx.accounts.token_interest.interest, clock.unix_timestamp);
+        staking_pool.update_coverage(ctx.accounts.staking_vault_associated_address.amount);
+        staking_pool.circuit_breaker = staking_pool.is_undercovered();
+
+        emit!(TokensDeposited {
+            admin: ctx.accounts.owner.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: deposit_amount,
+            time_stamp: clock.unix_timestamp,
+        });
+
         Ok(())
     }
 
+    //Compare vault balance against liabilities, callable by anyone
+    pub fn check_solvency(
+        ctx: Context<CheckSolvency>
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
 
- }
-#[derive(Accounts)]
-#[instruction(action_amount: u64, action_token: Pubkey, stake_action: bool, count: u8)]
-pub struct PerformAction<'info> {
-    #[account(mut)]
-    staker: Signer<'info>, 
+        staking_pool.accrue_interest(ctx.accounts.token_interest.interest, clock.unix_timestamp);
+        staking_pool.update_coverage(ctx.accounts.staking_vault_associated_address.amount);
 
-    #[account(
-        init_if_needed,
-        payer = staker,
-        space = 8 + 32 + 8,
-        seeds = [
-            b"stake_pool".as_ref(),
-            token_mint.key().as_ref()
-        ],
+        //Trip the circuit breaker, only deposit_token can lift it
+        if staking_pool.is_undercovered() {
+            staking_pool.circuit_breaker = true;
+        }
+
+        msg!("Coverage Ratio: {} bps, Circuit Breaker: {}", staking_pool.coverage_ratio, staking_pool.circuit_breaker);
+
+        Ok(())
+    }
+
+    //Reconcile the recorded pool amount with the real vault balance
+    pub fn sync_pool(
+        ctx: Context<SyncPool>
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let vault_amount = ctx.accounts.staking_vault_associated_address.amount;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+
+        let recorded_amount = staking_pool.token_amount;
+        staking_pool.token_amount = vault_amount;
+
+        //Book the vault against liabilities including unpaid interest
+        staking_pool.accrue_interest(ctx.accounts.token_interest.interest, current_time);
+        let liabilities = staking_pool.liabilities();
+        staking_pool.surplus = vault_amount.saturating_sub(liabilities);
+        staking_pool.shortfall = liabilities.saturating_sub(vault_amount);
+        staking_pool.last_sync_time = current_time;
+
+        emit!(PoolSynced {
+            token_mint: ctx.accounts.token_mint.key(),
+            recorded_amount,
+            vault_amount,
+            liabilities,
+            surplus: staking_pool.surplus,
+            shortfall: staking_pool.shortfall,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Release lots before the end of their lock at a penalty that shrinks linearly towards maturity
+    pub fn early_withdraw(
+        ctx: Context<EarlyWithdraw>,
+        action_amount: u64
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+        let penalty_config = ctx.accounts.penalty_config.clone();
+
+        let token_mint_key = ctx.accounts.token_mint.key();
+        let current_staking_pool_account = ctx.accounts.current_staking_pool.to_account_info();
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        let pool_action = &mut ctx.accounts.pool_action;
+        let locked_pool_action = &mut ctx.accounts.lock_pool_action;
+        let withdraw_pool_action = &mut ctx.accounts.withdraw_pool_action;
+
+        staking_pool.accrue_interest(current_interest, current_time);
+
+        // Withdraw request can only be made from Monday to Friday 
+        let day_of_week = (current_time/86400 + 4)%7;
+        require!(day_of_week != 1, ErrorCode::InvalidWithdrawDay);
+
+        let locked_before: u64 = locked_pool_action.locked_amount.iter().sum();
+        let mut skipped_locked = false;
+        let mut released_amount: u64 = 0;
+        let mut realized_interest: u64 = 0;
+        let mut penalty_amount: u64 = 0;
+
+        for n in 0..locked_pool_action.locked_amount.len() {
+            if released_amount == action_amount {
+                break;
+            }
+            let amount = locked_pool_action.locked_amount[n];
+            if amount == 0 {
+                continue;
+            }
+            //Gifted lots stay put until their lock date
+            if locked_pool_action.lock_of(n) > current_time {
+                skipped_locked = true;
+                continue;
+            }
+
+            let start_time = locked_pool_action.locked_start_time[n];
+            let (principal_taken, interest_taken, penalty) = release_lot_early(
+                amount,
+                start_time,
+                staking_pool.interest_elapsed(locked_pool_action.interest_start_of(n), current_time),
+                current_interest,
+                current_time,
+                action_amount - released_amount,
+                &penalty_config
+            );
+            locked_pool_action.locked_amount[n] -= principal_taken;
+            if locked_pool_action.locked_amount[n] == 0 {
+                locked_pool_action.locked_start_time[n] = 0;
+            }
+            released_amount += principal_taken + interest_taken;
+            realized_interest += interest_taken;
+            penalty_amount += penalty;
+        }
+        require!(released_amount == action_amount || !skipped_locked, ErrorCode::StakeLockedUntil);
+        require!(released_amount == action_amount, ErrorCode::NotEnoughToken);
+
+        //A penalty never drops below the floor, nor above what is withdrawn
+        if penalty_amount > 0 {
+            penalty_amount = penalty_amount.max(penalty_config.penalty_floor).min(action_amount);
+        }
+
+        //Only the amount left after the penalty is queued for the staker
+        let net_amount = action_amount - penalty_amount;
+        withdraw_pool_action.requested_amount += net_amount;
+        withdraw_pool_action.requested_time = current_time;
+
+        let token_amount = pool_action.token_amount.saturating_sub(action_amount);
+        staking_pool.set_weight(pool_action, token_amount, current_time);
+        pool_action.touch(current_time);
+
+        let locked_after: u64 = locked_pool_action.locked_amount.iter().sum();
+        staking_pool.book_withdraw_request(locked_before, locked_after, realized_interest, net_amount);
+
+        if penalty_amount > 0 {
+            if penalty_config.penalty_destination == PENALTY_REDISTRIBUTE {
+                //Stays in the vault and is credited to the remaining stakers
+                let distributed_amount = staking_pool.distribute_rewards(penalty_amount);
+                staking_pool.total_penalty_distributed += distributed_amount;
+
+                if distributed_amount > 0 {
+                    emit!(RewardsDistributed {
+                        token_mint: token_mint_key,
+                        amount: distributed_amount,
+                        source: REWARD_SOURCE_PENALTY,
+                        reward_per_share: staking_pool.reward_per_share,
+                        time_stamp: current_time,
+                    });
+                }
+            }
+            else {
+                let bump_seed_staking_pool = ctx.bumps.get("current_staking_pool").unwrap().to_le_bytes();
+                let staking_pool_signer_seeds: &[&[_]] = &[
+                    b"stake_pool".as_ref(),
+                    token_mint_key.as_ref(),
+                    &bump_seed_staking_pool
+                ];
+
+                let transfer_instruction = TransferChecked{
+                    from: ctx.accounts.staking_vault_associated_address.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.penalty_destination_account.to_account_info(),
+                    authority: current_staking_pool_account,
+                };
+
+                let signer = &[staking_pool_signer_seeds];
+                let cpi_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    transfer_instruction,
+                    signer,
+                );
+                transfer_checked(cpi_ctx, penalty_amount, staking_pool.decimals)?;
+
+                staking_pool.token_amount -= penalty_amount;
+            }
+        }
+
+        record_entry(
+            &mut ctx.accounts.pool_entry,
+            &mut ctx.accounts.pool_count,
+            ctx.accounts.staker.key(),
+            net_amount,
+            false,
+            current_time
+        );
+
+        emit!(EarlyWithdrawn {
+            staker: ctx.accounts.staker.key(),
+            token_mint: token_mint_key,
+            amount: action_amount,
+            interest_amount: realized_interest,
+            penalty_amount,
+            penalty_destination: penalty_config.penalty_destination,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_penalty_config(
+        ctx: Context<UpdatePenaltyConfig>,
+        penalty_rate: u64,
+        forfeit_interest: bool,
+        penalty_destination: u8,
+        treasury: Pubkey,
+        insurance_fund: Pubkey,
+        penalty_floor: u64,
+        amount_decimals: u8
+    ) -> Result<()> {
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+        require!(penalty_rate <= 10000, ErrorCode::InvalidPenaltyConfig);
+        require!(penalty_destination <= PENALTY_REDISTRIBUTE, ErrorCode::InvalidPenaltyConfig);
+
+        //Floor is given with `amount_decimals` fractional digits, 0 for whole tokens
+        let penalty_floor = to_base_units(penalty_floor, amount_decimals, ctx.accounts.current_staking_pool.decimals)?;
+
+        let penalty_config = &mut ctx.accounts.penalty_config;
+        penalty_config.penalty_floor = penalty_floor;
+        penalty_config.penalty_rate = penalty_rate;
+        penalty_config.forfeit_interest = forfeit_interest;
+        penalty_config.penalty_destination = penalty_destination;
+        penalty_config.treasury = treasury;
+        penalty_config.insurance_fund = insurance_fund;
+
+        Ok(())
+    }
+
+    //Credit admin-deposited yield to stakers by weight, instantly or streamed over `stream_duration` seconds
+    pub fn deposit_rewards(
+        ctx: Context<DepositRewards>,
+        deposit_amount: u64,
+        stream_duration: i64
+    ) -> Result<()>{
+        require!(ctx.accounts.owner.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+        require!(stream_duration >= 0, ErrorCode::InvalidRewardSchedule);
+
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+
+        //A stream would replace the window of a running schedule, top that up instead
+        if stream_duration > 0 {
+            let staking_pool = &mut ctx.accounts.current_staking_pool;
+            staking_pool.update_rewards(current_time);
+            require!(staking_pool.stream_remaining == 0, ErrorCode::RewardScheduleActive);
+        }
+
+        //Transfer Funds
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.admin_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+        transfer_checked(cpi_ctx, deposit_amount, ctx.accounts.current_staking_pool.decimals)?;
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.token_amount += deposit_amount;
+        staking_pool.total_rewards_deposited += deposit_amount;
+
+        if stream_duration == 0 {
+            staking_pool.update_rewards(current_time);
+            let distributed_amount = staking_pool.distribute_rewards(deposit_amount);
+
+            if distributed_amount > 0 {
+                emit!(RewardsDistributed {
+                    token_mint: ctx.accounts.token_mint.key(),
+                    amount: distributed_amount,
+                    source: REWARD_SOURCE_DEPOSIT,
+                    reward_per_share: staking_pool.reward_per_share,
+                    time_stamp: current_time,
+                });
+            }
+        }
+        else {
+            staking_pool.start_stream(deposit_amount, stream_duration, current_time);
+        }
+
+        emit!(RewardsDeposited {
+            admin: ctx.accounts.owner.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: deposit_amount,
+            reward_end: staking_pool.reward_end,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Emit a reward budget linearly between `reward_start` and `reward_end`, funded from unallocated vault tokens
+    pub fn create_reward_schedule(
+        ctx: Context<UpdateRewardSchedule>,
+        reward_budget: u64,
+        reward_start: i64,
+        reward_end: i64
+    ) -> Result<()>{
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+
+        require!(reward_start >= current_time && reward_end > reward_start, ErrorCode::InvalidRewardSchedule);
+
+        staking_pool.accrue_interest(ctx.accounts.token_interest.interest, current_time);
+        staking_pool.update_rewards(current_time);
+        require!(staking_pool.stream_remaining == 0, ErrorCode::RewardScheduleActive);
+        require!(staking_pool.unallocated_amount(ctx.accounts.staking_vault_associated_address.amount) >= reward_budget, ErrorCode::InsufficientRewardFunding);
+
+        staking_pool.stream_remaining = reward_budget;
+        staking_pool.reward_start = reward_start;
+        staking_pool.reward_end = reward_end;
+        staking_pool.update_reward_rate(current_time);
+        staking_pool.total_rewards_deposited += reward_budget;
+
+        emit!(RewardScheduleUpdated {
+            token_mint: ctx.accounts.token_mint.key(),
+            reward_start: staking_pool.reward_start,
+            reward_end: staking_pool.reward_end,
+            stream_remaining: staking_pool.stream_remaining,
+            reward_rate: staking_pool.reward_rate,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Add budget to the running schedule, emitted over what is left of it
+    pub fn top_up_reward_schedule(
+        ctx: Context<UpdateRewardSchedule>,
+        top_up_amount: u64
+    ) -> Result<()>{
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+
+        require!(staking_pool.reward_end > current_time, ErrorCode::InvalidRewardSchedule);
+
+        staking_pool.accrue_interest(ctx.accounts.token_interest.interest, current_time);
+        staking_pool.update_rewards(current_time);
+        require!(staking_pool.unallocated_amount(ctx.accounts.staking_vault_associated_address.amount) >= top_up_amount, ErrorCode::InsufficientRewardFunding);
+
+        staking_pool.stream_remaining += top_up_amount;
+        staking_pool.update_reward_rate(current_time);
+        staking_pool.total_rewards_deposited += top_up_amount;
+
+        emit!(RewardScheduleUpdated {
+            token_mint: ctx.accounts.token_mint.key(),
+            reward_start: staking_pool.reward_start,
+            reward_end: staking_pool.reward_end,
+            stream_remaining: staking_pool.stream_remaining,
+            reward_rate: staking_pool.reward_rate,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Push back the end of the running schedule, lowering the emission rate
+    pub fn extend_reward_schedule(
+        ctx: Context<UpdateRewardSchedule>,
+        reward_end: i64
+    ) -> Result<()>{
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+
+        require!(staking_pool.reward_end > current_time && reward_end > staking_pool.reward_end, ErrorCode::InvalidRewardSchedule);
+
+        staking_pool.update_rewards(current_time);
+        staking_pool.reward_end = reward_end;
+        staking_pool.update_reward_rate(current_time);
+
+        emit!(RewardScheduleUpdated {
+            token_mint: ctx.accounts.token_mint.key(),
+            reward_start: staking_pool.reward_start,
+            reward_end: staking_pool.reward_end,
+            stream_remaining: staking_pool.stream_remaining,
+            reward_rate: staking_pool.reward_rate,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Fund the interest budget, resuming accrual if it had run out
+    pub fn fund_interest_budget(
+        ctx: Context<FundInterestBudget>,
+        deposit_amount: u64
+    ) -> Result<()>{
+        require!(ctx.accounts.owner.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+
+        //Transfer Funds
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.admin_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+        transfer_checked(cpi_ctx, deposit_amount, ctx.accounts.current_staking_pool.decimals)?;
+
+        let current_interest = ctx.accounts.token_interest.interest;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.accrue_interest(current_interest, current_time);
+        staking_pool.token_amount += deposit_amount;
+        staking_pool.interest_budget += deposit_amount;
+        staking_pool.interest_unbudgeted = false;
+
+        if staking_pool.interest_paused && staking_pool.interest_budget > 0 {
+            staking_pool.interest_paused = false;
+            staking_pool.interest_paused_seconds += (current_time - staking_pool.interest_paused_at).max(0);
+        }
+
+        emit!(InterestRunway {
+            token_mint: ctx.accounts.token_mint.key(),
+            interest_budget: staking_pool.interest_budget,
+            interest: current_interest,
+            total_staked: staking_pool.total_staked,
+            runway: staking_pool.interest_runway(current_interest),
+            interest_paused: staking_pool.interest_paused,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Report the remaining interest budget and how long it lasts at the current rate, callable by anyone
+    pub fn get_interest_runway(
+        ctx: Context<CheckSolvency>
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+
+        staking_pool.accrue_interest(current_interest, current_time);
+        let runway = staking_pool.interest_runway(current_interest);
+
+        msg!("Interest Budget: {}, Runway: {} seconds", staking_pool.interest_budget, runway);
+
+        emit!(InterestRunway {
+            token_mint: ctx.accounts.token_mint.key(),
+            interest_budget: staking_pool.interest_budget,
+            interest: current_interest,
+            total_staked: staking_pool.total_staked,
+            runway,
+            interest_paused: staking_pool.interest_paused,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Report the stake limits and how much more the pool accepts, callable by anyone
+    pub fn get_stake_capacity(
+        ctx: Context<CheckSolvency>
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+
+        staking_pool.accrue_interest(current_interest, current_time);
+        let remaining_capacity = staking_pool.remaining_capacity();
+
+        msg!("Total Staked: {}, Remaining Capacity: {}", staking_pool.total_staked, remaining_capacity);
+
+        emit!(StakeCapacity {
+            token_mint: ctx.accounts.token_mint.key(),
+            min_stake: staking_pool.min_stake,
+            max_stake_per_user: staking_pool.max_stake_per_user,
+            pool_capacity: staking_pool.pool_capacity,
+            total_staked: staking_pool.total_staked,
+            remaining_capacity,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Pay out the staker's share of distributed rewards
+    pub fn claim_rewards(
+        ctx: Context<ClaimRewards>
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let token_mint_key = ctx.accounts.token_mint.key();
+        let current_staking_pool_account = ctx.accounts.current_staking_pool.to_account_info();
+
+        //Pay to the registered payout address, or to the staker's own account if none
+        let payout_config = &mut ctx.accounts.payout_config;
+        payout_config.apply_pending(clock.unix_timestamp);
+        if payout_config.payout_address == Pubkey::default() {
+            require!(ctx.accounts.payout_owner.key() == ctx.accounts.staker.key(), ErrorCode::InvalidPayoutAddress);
+        }
+        else {
+            require!(ctx.accounts.staker_associated_address.key() == payout_config.payout_address, ErrorCode::InvalidPayoutAddress);
+        }
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        let pool_action = &mut ctx.accounts.pool_action;
+
+        //Settle without changing the weight
+        let token_amount = pool_action.token_amount;
+        staking_pool.set_weight(pool_action, token_amount, clock.unix_timestamp);
+
+        let reward_amount = pool_action.pending_reward;
+        require!(reward_amount > 0, ErrorCode::NothingToClaim);
+
+        let bump_seed_staking_pool = ctx.bumps.get("current_staking_pool").unwrap().to_le_bytes();
+        let staking_pool_signer_seeds: &[&[_]] = &[
+            b"stake_pool".as_ref(),
+            token_mint_key.as_ref(),
+            &bump_seed_staking_pool
+        ];
+
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.staker_associated_address.to_account_info(),
+            authority: current_staking_pool_account,
+        };
+
+        let signer = &[staking_pool_signer_seeds];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+            signer,
+        );
+        transfer_checked(cpi_ctx, reward_amount, staking_pool.decimals)?;
+
+        pool_action.pending_reward = 0;
+        staking_pool.reward_liability = staking_pool.reward_liability.saturating_sub(reward_amount);
+        staking_pool.token_amount = staking_pool.token_amount.saturating_sub(reward_amount);
+
+        emit!(RewardsClaimed {
+            staker: ctx.accounts.staker.key(),
+            token_mint: token_mint_key,
+            amount: reward_amount,
+            time_stamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    //Settle interest on unlocked lots and fold them into a single lot
+    //The merged lot stays matured, only its interest restarts
+    pub fn merge_lots(
+        ctx: Context<MergeLots>
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+
+        //Older accounts need room for the fields of every lot
+        let lots = ctx.accounts.lock_pool_action.locked_amount.len();
+        realloc_locked_pool(
+            &ctx.accounts.lock_pool_action.to_account_info(),
+            &ctx.accounts.staker.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            lots
+        )?;
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        let pool_action = &mut ctx.accounts.pool_action;
+        let locked_pool_action = &mut ctx.accounts.lock_pool_action;
+
+        staking_pool.accrue_interest(current_interest, current_time);
+        locked_pool_action.pad_lots();
+
+        let mut merged_amount: u64 = 0;
+        let mut interest_amount: u64 = 0;
+        let mut merged_lots: u64 = 0;
+        let mut merged_start_time: i64 = 0;
+        let mut locked_amounts = Vec::new();
+        let mut locked_start_times = Vec::new();
+        let mut locked_untils = Vec::new();
+        let mut locked_interest_starts = Vec::new();
+
+        for n in 0..locked_pool_action.locked_amount.len() {
+            let amount = locked_pool_action.locked_amount[n];
+            let start_time = locked_pool_action.locked_start_time[n];
+            let locked_until = locked_pool_action.locked_until[n];
+            let interest_start = locked_pool_action.locked_interest_start[n];
+
+            //Drop the holes left behind by withdraw requests
+            if amount == 0 {
+                continue;
+            }
+
+            //Gifted lots keep their own lock date
+            if start_time + LOCKING_PERIOD < current_time && locked_until <= current_time {
+                let interest = calculate_interest(amount, current_interest, staking_pool.interest_elapsed(interest_start, current_time));
+                interest_amount += interest;
+                merged_amount += amount + interest;
+                merged_lots += 1;
+                merged_start_time = merged_start_time.max(start_time);
+            }
+            else {
+                locked_amounts.push(amount);
+                locked_start_times.push(start_time);
+                locked_untils.push(locked_until);
+                locked_interest_starts.push(interest_start);
+            }
+        }
+
+        if merged_amount > 0 {
+            locked_amounts.push(merged_amount);
+            locked_start_times.push(merged_start_time);
+            locked_untils.push(0);
+            locked_interest_starts.push(staking_pool.interest_clock(current_time));
+        }
+        locked_pool_action.locked_amount = locked_amounts;
+        locked_pool_action.locked_start_time = locked_start_times;
+        locked_pool_action.locked_until = locked_untils;
+        locked_pool_action.locked_interest_start = locked_interest_starts;
+        let lots = locked_pool_action.locked_amount.len();
+
+        //Settled interest becomes principal and carries weight
+        staking_pool.total_staked += interest_amount;
+        staking_pool.accrued_interest = staking_pool.accrued_interest.saturating_sub(interest_amount);
+        let token_amount = pool_action.token_amount + interest_amount;
+        staking_pool.set_weight(pool_action, token_amount, current_time);
+
+        shrink_locked_pool(
+            &ctx.accounts.lock_pool_action.to_account_info(),
+            &ctx.accounts.staker.to_account_info(),
+            lots
+        )?;
+
+        emit!(LotsMerged {
+            staker: ctx.accounts.staker.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            merged_lots,
+            amount: merged_amount,
+            interest_amount,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Create an empty zero-copy position for high-frequency stakers
+    pub fn init_position(
+        ctx: Context<InitPosition>
+    ) -> Result<()>{
+        let mut lot_position = ctx.accounts.lot_position.load_init()?;
+        lot_position.owner = ctx.accounts.staker.key();
+
+        Ok(())
+    }
+
+    //Move the lots of an existing LockedPool into a zero-copy position and close it
+    pub fn migrate_position(
+        ctx: Context<MigratePosition>
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let locked_pool_action = &ctx.accounts.lock_pool_action;
+        let mut lot_position = ctx.accounts.lot_position.load_init()?;
+        lot_position.owner = ctx.accounts.staker.key();
+
+        let mut lot_count = 0;
+        for n in 0..locked_pool_action.locked_amount.len() {
+            //Skip the holes left behind by withdraw requests
+            if locked_pool_action.locked_amount[n] == 0 {
+                continue;
+            }
+            require!(lot_count < POSITION_LOTS, ErrorCode::PositionFull);
+            //Zero-copy lots carry no lock date
+            require!(locked_pool_action.lock_of(n) <= clock.unix_timestamp, ErrorCode::StakeLockedUntil);
+
+            let lot = &mut lot_position.lots[lot_count];
+            lot.amount = locked_pool_action.locked_amount[n];
+            lot.start_time = locked_pool_action.locked_start_time[n];
+            lot.interest_start = locked_pool_action.interest_start_of(n);
+            lot.flags = LOT_ACTIVE;
+            lot_count += 1;
+        }
+
+        Ok(())
+    }
+
+    //Stake into a zero-copy position, no history entry is written
+    pub fn stake_position(
+        ctx: Context<StakePosition>,
+        action_amount: u64
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+
+        let vault_before = ctx.accounts.staking_vault_associated_address.amount;
+
+        //Transfer Funds
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.staker_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            authority: ctx.accounts.staker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+        transfer_checked(cpi_ctx, action_amount, ctx.accounts.current_staking_pool.decimals)?;
+
+        //Credit what reached the vault, not the nominal amount
+        ctx.accounts.staking_vault_associated_address.reload()?;
+        let action_amount = ctx.accounts.current_staking_pool.received_amount(action_amount, vault_before, ctx.accounts.staking_vault_associated_address.amount)?;
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        let pool_action = &mut ctx.accounts.pool_action;
+        let mut lot_position = ctx.accounts.lot_position.load_mut()?;
+
+        staking_pool.accrue_interest(current_interest, current_time);
+        require!(!staking_pool.circuit_breaker, ErrorCode::PoolUndercollateralized);
+        staking_pool.check_stake_limits(action_amount, pool_action.staked())?;
+
+        //Take the first free lot
+        let lot = lot_position.lots.iter_mut().find(|lot| lot.flags & LOT_ACTIVE == 0).ok_or(ErrorCode::PositionFull)?;
+        lot.amount = action_amount;
+        lot.start_time = current_time;
+        lot.interest_start = staking_pool.interest_clock(current_time);
+        lot.tier = 0;
+        lot.flags = LOT_ACTIVE;
+
+        staking_pool.token_amount += action_amount;
+        staking_pool.total_staked += action_amount;
+
+        let token_amount = pool_action.token_amount + action_amount;
+        staking_pool.set_weight(pool_action, token_amount, current_time);
+        pool_action.touch(current_time);
+
+        emit!(Staked {
+            staker: ctx.accounts.staker.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: action_amount,
+            interest_rate: current_interest,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Queue a withdraw from a zero-copy position, claimed through claim_withdraw
+    pub fn request_withdraw_position(
+        ctx: Context<RequestWithdrawPosition>,
+        action_amount: u64
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        let pool_action = &mut ctx.accounts.pool_action;
+        let withdraw_pool_action = &mut ctx.accounts.withdraw_pool_action;
+        let mut lot_position = ctx.accounts.lot_position.load_mut()?;
+
+        staking_pool.accrue_interest(current_interest, current_time);
+
+        // Withdraw request can only be made from Monday to Friday 
+        let day_of_week = (current_time/86400 + 4)%7;
+        require!(day_of_week != 1, ErrorCode::InvalidWithdrawDay);
+
+        let withdraw_action_amount = withdraw_pool_action.requested_amount + action_amount;
+        let locked_before: u64 = lot_position.lots.iter().map(|lot| lot.amount).sum();
+        let mut realized_interest: u64 = 0;
+        let interest_clock = staking_pool.interest_clock(current_time);
+
+        //Immature lots only leave through early_withdraw
+        for lot in lot_position.lots.iter_mut().filter(|lot| lot.flags & LOT_ACTIVE != 0 && lot_matured(lot.start_time, current_time)) {
+            let (amount, start_time, interest_amount) = settle_lot(
+                lot.amount,
+                lot.start_time,
+                staking_pool.interest_elapsed(lot.interest_start, current_time),
+                current_interest,
+                &mut withdraw_pool_action.requested_amount,
+                withdraw_action_amount
+            );
+            realized_interest += interest_amount;
+
+            if amount == 0 {
+                *lot = Lot::default();
+            }
+            else {
+                lot.amount = amount;
+                lot.start_time = start_time;
+                lot.interest_start = interest_clock;
+            }
+        }
+        require!(withdraw_pool_action.requested_amount >= action_amount, ErrorCode::LockingPeriod);
+        require!(staking_pool.token_amount >= action_amount, ErrorCode::NotEnoughToken);
+
+        let token_amount = pool_action.token_amount.saturating_sub(action_amount);
+        staking_pool.set_weight(pool_action, token_amount, current_time);
+        pool_action.touch(current_time);
+        withdraw_pool_action.requested_time = current_time;
+
+        let locked_after: u64 = lot_position.lots.iter().map(|lot| lot.amount).sum();
+        staking_pool.book_withdraw_request(locked_before, locked_after, realized_interest, action_amount);
+
+        let interest_amount = realized_interest.min(action_amount);
+        emit!(WithdrawRequested {
+            staker: ctx.accounts.staker.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: action_amount,
+            principal_amount: action_amount - interest_amount,
+            interest_amount,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Stake into a single-lot position backed by a freshly minted 1-of-1 NFT
+    pub fn stake_nft_position(
+        ctx: Context<StakeNftPosition>,
+        action_amount: u64
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+        let token_mint_key = ctx.accounts.token_mint.key();
+        let current_staking_pool_account = ctx.accounts.current_staking_pool.to_account_info();
+
+        let vault_before = ctx.accounts.staking_vault_associated_address.amount;
+
+        //Transfer Funds
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.staker_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            authority: ctx.accounts.staker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+        transfer_checked(cpi_ctx, action_amount, ctx.accounts.current_staking_pool.decimals)?;
+
+        //Credit what reached the vault, not the nominal amount
+        ctx.accounts.staking_vault_associated_address.reload()?;
+        let action_amount = ctx.accounts.current_staking_pool.received_amount(action_amount, vault_before, ctx.accounts.staking_vault_associated_address.amount)?;
+
+        //Mint the position NFT and drop the mint authority so no second copy can exist
+        let bump_seed_staking_pool = ctx.bumps.get("current_staking_pool").unwrap().to_le_bytes();
+        let staking_pool_signer_seeds: &[&[_]] = &[
+            b"stake_pool".as_ref(),
+            token_mint_key.as_ref(),
+            &bump_seed_staking_pool
+        ];
+        let signer = &[staking_pool_signer_seeds];
+
+        let mint_instruction = MintTo{
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.staker_nft_address.to_account_info(),
+            authority: current_staking_pool_account.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            mint_instruction,
+            signer,
+        );
+        anchor_spl::token::mint_to(cpi_ctx, 1)?;
+
+        let authority_instruction = SetAuthority{
+            current_authority: current_staking_pool_account,
+            account_or_mint: ctx.accounts.nft_mint.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            authority_instruction,
+            signer,
+        );
+        anchor_spl::token::set_authority(cpi_ctx, anchor_spl::token::spl_token::instruction::AuthorityType::MintTokens, None)?;
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.accrue_interest(current_interest, current_time);
+        require!(!staking_pool.circuit_breaker, ErrorCode::PoolUndercollateralized);
+        let pool_action = &mut ctx.accounts.pool_action;
+        staking_pool.check_stake_limits(action_amount, pool_action.staked())?;
+        pool_action.position_staked += action_amount;
+        staking_pool.token_amount += action_amount;
+        staking_pool.total_staked += action_amount;
+
+        let nft_position = &mut ctx.accounts.nft_position;
+        nft_position.owner = ctx.accounts.staker.key();
+        nft_position.nft_mint = ctx.accounts.nft_mint.key();
+        nft_position.token_mint = token_mint_key;
+        nft_position.amount = action_amount;
+        nft_position.start_time = current_time;
+        nft_position.interest_start = staking_pool.interest_clock(current_time);
+
+        emit!(Staked {
+            staker: ctx.accounts.staker.key(),
+            token_mint: token_mint_key,
+            amount: action_amount,
+            interest_rate: current_interest,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Rebind an NFT position to whoever holds its NFT now
+    pub fn transfer_position(
+        ctx: Context<TransferPosition>
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let nft_position = &mut ctx.accounts.nft_position;
+
+        //Both pool actions would be the same account, and the holder's count would double
+        require!(ctx.accounts.holder.key() != nft_position.owner, ErrorCode::PositionAlreadyBound);
+
+        let previous_owner = nft_position.owner;
+        nft_position.owner = ctx.accounts.holder.key();
+
+        //The position counts for its holder, without checking their limit so the rebind never fails
+        let previous_pool_action = &mut ctx.accounts.previous_pool_action;
+        previous_pool_action.position_staked = previous_pool_action.position_staked.saturating_sub(nft_position.amount);
+        ctx.accounts.pool_action.position_staked += nft_position.amount;
+
+        emit!(PositionTransferred {
+            nft_mint: nft_position.nft_mint,
+            previous_owner,
+            new_owner: nft_position.owner,
+            time_stamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    //Queue a withdraw from an NFT position, only its holder can
+    pub fn request_withdraw_nft_position(
+        ctx: Context<NftPositionAction>,
+        action_amount: u64
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let current_interest = ctx.accounts.token_interest.interest;
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        let nft_position = &mut ctx.accounts.nft_position;
+
+        staking_pool.accrue_interest(current_interest, current_time);
+
+        // Withdraw request can only be made from Monday to Friday 
+        let day_of_week = (current_time/86400 + 4)%7;
+        require!(day_of_week != 1, ErrorCode::InvalidWithdrawDay);
+
+        require!(lot_matured(nft_position.start_time, current_time), ErrorCode::LockingPeriod);
+
+        let locked_before = nft_position.amount;
+        let withdraw_action_amount = nft_position.requested_amount + action_amount;
+        let (amount, start_time, interest_amount) = settle_lot(
+            nft_position.amount,
+            nft_position.start_time,
+            staking_pool.interest_elapsed(nft_position.interest_start, current_time),
+            current_interest,
+            &mut nft_position.requested_amount,
+            withdraw_action_amount
+        );
+        require!(nft_position.requested_amount >= withdraw_action_amount, ErrorCode::LockingPeriod);
+        require!(staking_pool.token_amount >= action_amount, ErrorCode::NotEnoughToken);
+
+        nft_position.amount = amount;
+        nft_position.start_time = start_time;
+        nft_position.interest_start = staking_pool.interest_clock(current_time);
+        nft_position.requested_time = current_time;
+        staking_pool.book_withdraw_request(locked_before, amount, interest_amount, action_amount);
+
+        let interest_amount = interest_amount.min(action_amount);
+        let pool_action = &mut ctx.accounts.pool_action;
+        pool_action.position_staked = pool_action.position_staked.saturating_sub(action_amount - interest_amount);
+
+        emit!(WithdrawRequested {
+            staker: ctx.accounts.holder.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: action_amount,
+            principal_amount: action_amount - interest_amount,
+            interest_amount,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Pay out a requested withdraw of an NFT position to its holder
+    pub fn claim_nft_position(
+        ctx: Context<NftPositionAction>,
+        claim_amount: u64
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let token_mint_key = ctx.accounts.token_mint.key();
+        let current_staking_pool_account = ctx.accounts.current_staking_pool.to_account_info();
+
+        require!(ctx.accounts.nft_position.requested_amount >= claim_amount, ErrorCode::NotEnoughToken);
+        require!(ctx.accounts.current_staking_pool.token_amount >= claim_amount, ErrorCode::ExceedPoolAmount);
+
+        //Transfer Funds
+        let bump_seed_staking_pool = ctx.bumps.get("current_staking_pool").unwrap().to_le_bytes();
+        let staking_pool_signer_seeds: &[&[_]] = &[
+            b"stake_pool".as_ref(),
+            token_mint_key.as_ref(),
+            &bump_seed_staking_pool
+        ];
+
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.staking_vault_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.holder_associated_address.to_account_info(),
+            authority: current_staking_pool_account,
+        };
+
+        let signer = &[staking_pool_signer_seeds];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+            signer,
+        );
+        transfer_checked(cpi_ctx, claim_amount, ctx.accounts.current_staking_pool.decimals)?;
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.token_amount -= claim_amount;
+        staking_pool.pending_withdraw = staking_pool.pending_withdraw.saturating_sub(claim_amount);
+
+        let nft_position = &mut ctx.accounts.nft_position;
+        nft_position.requested_amount -= claim_amount;
+
+        emit!(WithdrawClaimed {
+            staker: ctx.accounts.holder.key(),
+            token_mint: token_mint_key,
+            amount: claim_amount,
+            remaining_requested: nft_position.requested_amount,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Close an old history entry and refund its rent to the staker
+    pub fn close_entry(
+        ctx: Context<CloseEntry>,
+        index: u64
+    ) -> Result<()>{
+        let clock = Clock::get()?;
+        let staker_key = ctx.accounts.staker.key();
+        let token_mint_key = ctx.accounts.token_mint.key();
+        let entry_info = ctx.accounts.pool_entry.to_account_info();
+
+        //Entries written before the counter was widened are seeded with a one byte index
+        let wide_entry = Pubkey::find_program_address(
+            &[b"pool_entry".as_ref(), staker_key.as_ref(), token_mint_key.as_ref(), &index.to_le_bytes()],
+            ctx.program_id
+        ).0;
+        let legacy_entry = u8::try_from(index).ok().map(|index| Pubkey::find_program_address(
+            &[b"pool_entry".as_ref(), staker_key.as_ref(), token_mint_key.as_ref(), &[index]],
+            ctx.program_id
+        ).0);
+        require!(entry_info.key() == wide_entry || Some(entry_info.key()) == legacy_entry, anchor_lang::error::ErrorCode::ConstraintSeeds);
+
+        let pool_action_entry = Account::<PoolActionEntry>::try_from(&entry_info)?;
+        require!(pool_action_entry.time_stamp + ENTRY_RETENTION_PERIOD <= clock.unix_timestamp, ErrorCode::EntryTooRecent);
+
+        pool_action_entry.close(ctx.accounts.staker.to_account_info())
+    }
+
+    //Close an exited position and refund its rent to the staker
+    //pool_count stays open so entry indices are never reused
+    pub fn close_position(
+        ctx: Context<ClosePosition>
+    ) -> Result<()>{
+        let staker = ctx.accounts.staker.to_account_info();
+        require!(ctx.accounts.withdraw_pool_action.requested_amount == 0, ErrorCode::PositionNotEmpty);
+
+        //Lots live in the LockedPool, the zero-copy position or both, migrate_position closes the LockedPool
+        let lock_pool_action = ctx.accounts.lock_pool_action.to_account_info();
+        if lock_pool_action.owner == &crate::ID {
+            let locked_pool_action = Account::<LockedPool>::try_from(&lock_pool_action)?;
+            require!(locked_pool_action.locked_amount.iter().all(|amount| *amount == 0), ErrorCode::PositionNotEmpty);
+            locked_pool_action.close(staker.clone())?;
+        }
+
+        let lot_position = ctx.accounts.lot_position.to_account_info();
+        if lot_position.owner == &crate::ID {
+            let lot_position = AccountLoader::<LotPosition>::try_from(&lot_position)?;
+            require!(lot_position.load()?.lots.iter().all(|lot| lot.flags & LOT_ACTIVE == 0), ErrorCode::PositionNotEmpty);
+            lot_position.close(staker)?;
+        }
+
+        //Drop the remaining weight, unclaimed rewards must be claimed first
+        let clock = Clock::get()?;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        let pool_action = &mut ctx.accounts.pool_action;
+        staking_pool.set_weight(pool_action, 0, clock.unix_timestamp);
+        require!(pool_action.pending_reward == 0, ErrorCode::PositionNotEmpty);
+        //NFT positions bound to the staker still count against the user limit
+        require!(pool_action.position_staked == 0, ErrorCode::PositionNotEmpty);
+
+        Ok(())
+    }
+
+    pub fn update_coverage_threshold(
+        ctx: Context<UpdatePoolConfig>,
+        min_coverage_ratio: u64
+    ) -> Result<()> {
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.min_coverage_ratio = min_coverage_ratio;
+
+        Ok(())
+    }
+
+    pub fn update_payout_delay(
+        ctx: Context<UpdatePoolConfig>,
+        payout_change_delay: i64
+    ) -> Result<()> {
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+        require!(payout_change_delay >= 0, ErrorCode::InvalidPayoutDelay);
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.payout_change_delay = payout_change_delay;
+
+        Ok(())
+    }
+
+    pub fn update_transfer_tolerance(
+        ctx: Context<UpdatePoolConfig>,
+        transfer_tolerance: u64
+    ) -> Result<()> {
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+        require!(transfer_tolerance <= 10000, ErrorCode::InvalidTransferTolerance);
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.transfer_tolerance = transfer_tolerance;
+
+        Ok(())
+    }
+
+    //Stake limits are given in whole tokens, 0 lifts the per-user maximum or the pool capacity
+    pub fn update_stake_limits(
+        ctx: Context<UpdatePoolConfig>,
+        min_stake: u64,
+        max_stake_per_user: u64,
+        pool_capacity: u64,
+        amount_decimals: u8
+    ) -> Result<()> {
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+        require!(max_stake_per_user == 0 || min_stake <= max_stake_per_user, ErrorCode::InvalidStakeLimits);
+        require!(pool_capacity == 0 || min_stake <= pool_capacity, ErrorCode::InvalidStakeLimits);
+
+        //Limits are given with `amount_decimals` fractional digits, 0 for whole tokens
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        let decimals = staking_pool.decimals;
+        staking_pool.min_stake = to_base_units(min_stake, amount_decimals, decimals)?;
+        staking_pool.max_stake_per_user = to_base_units(max_stake_per_user, amount_decimals, decimals)?;
+        staking_pool.pool_capacity = to_base_units(pool_capacity, amount_decimals, decimals)?;
+
+        Ok(())
+    }
+
+    //Register where claims are paid, the first address applies at once and later changes after the pool's delay
+    pub fn set_payout_address(
+        ctx: Context<SetPayoutAddress>
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let payout_address = ctx.accounts.payout_associated_address.key();
+        let payout_config = &mut ctx.accounts.payout_config;
+
+        payout_config.apply_pending(current_time);
+        if payout_config.payout_address == Pubkey::default() {
+            payout_config.payout_address = payout_address;
+            payout_config.payout_effective_time = current_time;
+        }
+        else {
+            payout_config.pending_payout_address = payout_address;
+            payout_config.payout_effective_time = current_time + ctx.accounts.current_staking_pool.payout_change_delay;
+        }
+
+        emit!(PayoutAddressUpdated {
+            staker: ctx.accounts.staker.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            payout_address,
+            effective_time: payout_config.payout_effective_time,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Drop a payout address change that has not applied yet
+    pub fn cancel_payout_address(
+        ctx: Context<CancelPayoutAddress>
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let payout_config = &mut ctx.accounts.payout_config;
+
+        payout_config.apply_pending(clock.unix_timestamp);
+        payout_config.pending_payout_address = Pubkey::default();
+
+        Ok(())
+    }
+
+    //Create the pool of a mint, only mints owned by the SPL Token program are supported
+    pub fn create_pool(
+        ctx: Context<CreatePool>
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
+        //Owner is checked by the context, so this is an SPL Token mint
+        let token_mint = Mint::try_deserialize(&mut &ctx.accounts.token_mint.try_borrow_data()?[..])?;
+
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.token_mint = ctx.accounts.token_mint.key();
+        staking_pool.decimals = token_mint.decimals;
+
+        emit!(PoolCreated {
+            admin: ctx.accounts.admin.key(),
+            token_mint: staking_pool.token_mint,
+            decimals: staking_pool.decimals,
+            time_stamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    //Grow a pool created by an older version of the program to the current layout, new fields start zeroed
+    pub fn migrate_pool(
+        ctx: Context<MigratePool>
+    ) -> Result<()> {
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
+        let pool_info = ctx.accounts.current_staking_pool.to_account_info();
+        if pool_info.data_len() >= StakePool::SPACE {
+            return Ok(());
+        }
+
+        realloc_account(
+            &pool_info,
+            &ctx.accounts.admin.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            StakePool::SPACE
+        )?;
+
+        //Pools from before the interest budget keep paying interest until the admin first funds one
+        let mut data = pool_info.try_borrow_mut_data()?;
+        let mut staking_pool = StakePool::try_deserialize(&mut &data[..])?;
+        staking_pool.interest_unbudgeted = true;
+
+        //The legacy pool only counted what it held for its stakers, book all of it as staked principal
+        //so coverage, sync and rescues see it as owed, claims of older requests draw it down again
+        staking_pool.total_staked = staking_pool.token_amount;
+        staking_pool.token_mint = ctx.accounts.token_mint.key();
+        staking_pool.decimals = ctx.accounts.token_mint.decimals;
+        staking_pool.try_serialize(&mut &mut data[..])?;
+
+        Ok(())
+    }
+
+    //Grow a staker's pool action created by an older version of the program to the current layout
+    //The pool has to be migrated first, and the old layout does not load anywhere else until this ran
+    pub fn migrate_pool_action(
+        ctx: Context<MigratePoolAction>
+    ) -> Result<()> {
+        let pool_action_info = ctx.accounts.pool_action.to_account_info();
+        if pool_action_info.data_len() >= PoolAction::SPACE {
+            return Ok(());
+        }
+
+        realloc_account(
+            &pool_action_info,
+            &ctx.accounts.staker.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            PoolAction::SPACE
+        )?;
+
+        //The legacy stake starts earning rewards now, without a claim on what was distributed before
+        let mut data = pool_action_info.try_borrow_mut_data()?;
+        let mut pool_action = PoolAction::try_deserialize(&mut &data[..])?;
+        let staking_pool = &mut ctx.accounts.current_staking_pool;
+        staking_pool.update_rewards(Clock::get()?.unix_timestamp);
+        staking_pool.total_weight += pool_action.token_amount;
+        pool_action.reward_debt = pool_action.token_amount as u128 * staking_pool.reward_per_share / REWARD_PRECISION;
+        pool_action.try_serialize(&mut &mut data[..])?;
+
+        Ok(())
+    }
+
+    //Widen a staker's entry counter from the old one byte layout, keeping its value
+    pub fn migrate_pool_count(
+        ctx: Context<MigratePoolCount>
+    ) -> Result<()> {
+        let pool_count = ctx.accounts.pool_count.to_account_info();
+        if pool_count.data_len() >= 8 + 8 {
+            return Ok(());
+        }
+
+        let count = pool_count.try_borrow_data()?[8] as u64;
+        realloc_account(
+            &pool_count,
+            &ctx.accounts.staker.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + 8
+        )?;
+        pool_count.try_borrow_mut_data()?[8..16].copy_from_slice(&count.to_le_bytes());
+
+        Ok(())
+    }
+
+    //Allowlist a stablecoin in the basket pool, the cap is given in whole tokens of the basket unit
+    pub fn set_basket_mint(
+        ctx: Context<SetBasketMint>,
+        cap: u64,
+        enabled: bool
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
+        let basket_mint = &mut ctx.accounts.basket_mint;
+        basket_mint.token_mint = ctx.accounts.token_mint.key();
+        basket_mint.decimals = ctx.accounts.token_mint.decimals;
+        basket_mint.cap = to_base_units(cap, 0, BASKET_DECIMALS)?;
+        basket_mint.enabled = enabled;
+
+        emit!(BasketMintUpdated {
+            token_mint: basket_mint.token_mint,
+            cap: basket_mint.cap,
+            enabled,
+            time_stamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_basket_interest(
+        ctx: Context<UpdateBasketInterest>,
+        interest: u8
+    ) -> Result<()> {
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
+        //Interest up to now is earned at the old rate
+        let basket_pool = &mut ctx.accounts.basket_pool;
+        basket_pool.accrue(Clock::get()?.unix_timestamp);
+        basket_pool.interest = interest;
+
+        Ok(())
+    }
+
+    //Fund the basket's interest budget with an allowlisted stablecoin, booked in the basket unit
+    pub fn fund_basket_interest(
+        ctx: Context<FundBasketInterest>,
+        deposit_amount: u64
+    ) -> Result<()> {
+        require!(ctx.accounts.admin.key() == ctx.accounts.admin_config.admin.key(), ErrorCode::InvalidAdmin);
+
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let decimals = ctx.accounts.basket_mint.decimals;
+
+        //Only move the part that maps exactly onto the basket unit
+        let normalized_amount = normalize_amount(deposit_amount, decimals, BASKET_DECIMALS)?;
+        let deposit_amount = normalize_amount(normalized_amount, BASKET_DECIMALS, decimals)?;
+        require!(normalized_amount > 0, ErrorCode::InvalidTokenAmount);
+
+        let vault_before = ctx.accounts.basket_vault.amount;
+
+        //Transfer Funds
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.admin_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.basket_vault.to_account_info(),
+            authority: ctx.accounts.admin.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+        transfer_checked(cpi_ctx, deposit_amount, decimals)?;
+
+        ctx.accounts.basket_vault.reload()?;
+        require!(ctx.accounts.basket_vault.amount - vault_before == deposit_amount, ErrorCode::TransferShortfall);
+
+        let basket_pool = &mut ctx.accounts.basket_pool;
+        basket_pool.accrue(current_time);
+        basket_pool.interest_budget += normalized_amount;
+
+        emit!(BasketInterestFunded {
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: deposit_amount,
+            normalized_amount,
+            interest_budget: basket_pool.interest_budget,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Stake an allowlisted stablecoin into the basket, booked in the basket unit
+    pub fn stake_basket(
+        ctx: Context<BasketAction>,
+        action_amount: u64
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let decimals = ctx.accounts.basket_mint.decimals;
+
+        require!(ctx.accounts.basket_mint.enabled, ErrorCode::BasketMintDisabled);
+
+        //Only move the part that maps exactly onto the basket unit
+        let normalized_amount = normalize_amount(action_amount, decimals, BASKET_DECIMALS)?;
+        let action_amount = normalize_amount(normalized_amount, BASKET_DECIMALS, decimals)?;
+        require!(normalized_amount > 0, ErrorCode::InvalidTokenAmount);
+
+        let basket_mint = &mut ctx.accounts.basket_mint;
+        require!(basket_mint.balance + normalized_amount <= basket_mint.cap, ErrorCode::BasketMintCapExceeded);
+        basket_mint.balance += normalized_amount;
+
+        let vault_before = ctx.accounts.basket_vault.amount;
+
+        //Transfer Funds
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.staker_associated_address.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.basket_vault.to_account_info(),
+            authority: ctx.accounts.staker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+        transfer_checked(cpi_ctx, action_amount, decimals)?;
+
+        //Stablecoins in the basket must arrive in full
+        ctx.accounts.basket_vault.reload()?;
+        require!(ctx.accounts.basket_vault.amount - vault_before == action_amount, ErrorCode::TransferShortfall);
+
+        let basket_pool = &mut ctx.accounts.basket_pool;
+        let basket_position = &mut ctx.accounts.basket_position;
+        basket_position.owner = ctx.accounts.staker.key();
+        basket_pool.accrue(current_time);
+        basket_position.settle(basket_pool.interest_index);
+        basket_position.amount += normalized_amount;
+        basket_pool.total_staked += normalized_amount;
+
+        emit!(BasketStaked {
+            staker: ctx.accounts.staker.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: action_amount,
+            normalized_amount,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    //Withdraw `normalized_amount` of the basket position in the chosen mint, interest goes first
+    pub fn withdraw_basket(
+        ctx: Context<BasketAction>,
+        normalized_amount: u64
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp;
+        let decimals = ctx.accounts.basket_mint.decimals;
+        let basket_pool_account = ctx.accounts.basket_pool.to_account_info();
+
+        let basket_pool = &mut ctx.accounts.basket_pool;
+        let basket_position = &mut ctx.accounts.basket_position;
+        basket_pool.accrue(current_time);
+        basket_position.settle(basket_pool.interest_index);
+        require!(basket_position.amount + basket_position.accrued_interest >= normalized_amount, ErrorCode::NotEnoughToken);
+
+        let interest_amount = normalized_amount.min(basket_position.accrued_interest);
+        basket_position.accrued_interest -= interest_amount;
+        basket_position.amount -= normalized_amount - interest_amount;
+        basket_pool.total_staked -= normalized_amount - interest_amount;
+
+        //Paid from the chosen mint's vault only
+        let withdraw_amount = normalize_amount(normalized_amount, BASKET_DECIMALS, decimals)?;
+        require!(withdraw_amount > 0, ErrorCode::InvalidTokenAmount);
+        require!(ctx.accounts.basket_vault.amount >= withdraw_amount, ErrorCode::InsufficientBasketLiquidity);
+
+        let basket_mint = &mut ctx.accounts.basket_mint;
+        basket_mint.balance = basket_mint.balance.saturating_sub(normalized_amount);
+
+        let bump_seed_basket_pool = ctx.bumps.get("basket_pool").unwrap().to_le_bytes();
+        let basket_pool_signer_seeds: &[&[_]] = &[
+            b"basket_pool".as_ref(),
+            &bump_seed_basket_pool
+        ];
+
+        let transfer_instruction = TransferChecked{
+            from: ctx.accounts.basket_vault.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.staker_associated_address.to_account_info(),
+            authority: basket_pool_account,
+        };
+
+        let signer = &[basket_pool_signer_seeds];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+            signer,
+        );
+        transfer_checked(cpi_ctx, withdraw_amount, decimals)?;
+
+        emit!(BasketWithdrawn {
+            staker: ctx.accounts.staker.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount: withdraw_amount,
+            normalized_amount,
+            interest_amount,
+            time_stamp: current_time,
+        });
+
+        Ok(())
+    }
+
+
+ }
+#[derive(Accounts)]
+#[instruction(action_amount: u64, action_token: Pubkey, stake_action: bool)]
+pub struct PerformAction<'info> {
+    #[account(mut)]
+    staker: Signer<'info>, 
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = PoolAction::SPACE,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 4 + 4 + 4 + 4 + 8*4*LOT_GROWTH,
+        seeds = [
+            b"lock_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lock_pool_action: Account<'info, LockedPool>,
+
+    
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8 + 8,
+        seeds = [
+            b"withdraw_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    withdraw_pool_action: Account<'info, WithdrawRequest>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8,
+        seeds = [
+            b"pool_count".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_count: Account<'info, Count>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + 32 + 8 + 1 + 8 + 1,
+        seeds = [
+            b"pool_entry".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref(),
+            &(pool_count.count + 1).to_le_bytes()
+        ],
+        bump
+    )]
+    pool_entry: Account<'info, PoolActionEntry>,
+
+    #[account(
+        init_if_needed, 
+        payer = staker, 
+        space = 8 + 8, 
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+         mut,
+        constraint= staker_associated_address.owner == staker.key(),
+        constraint= staker_associated_address.mint == token_mint.key(),
+    )]
+    staker_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+#[instruction(action_amount: u64)]
+pub struct Stake<'info> {
+    #[account(mut)]
+    staker: Signer<'info>, 
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = PoolAction::SPACE,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 4 + 4 + 4 + 4 + 8*4*LOT_GROWTH,
+        seeds = [
+            b"lock_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lock_pool_action: Account<'info, LockedPool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8,
+        seeds = [
+            b"pool_count".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_count: Account<'info, Count>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + 32 + 8 + 1 + 8 + 1,
+        seeds = [
+            b"pool_entry".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref(),
+            &(pool_count.count + 1).to_le_bytes()
+        ],
+        bump
+    )]
+    pool_entry: Account<'info, PoolActionEntry>,
+
+    #[account(
+        init_if_needed, 
+        payer = staker, 
+        space = 8 + 8, 
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint= staker_associated_address.owner == staker.key(),
+        constraint= staker_associated_address.mint == token_mint.key(),
+    )]
+    staker_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+#[instruction(action_amount: u64)]
+pub struct StakeSol<'info> {
+    #[account(mut)]
+    staker: Signer<'info>, 
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = PoolAction::SPACE,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 4 + 4 + 4 + 4 + 8*4*LOT_GROWTH,
+        seeds = [
+            b"lock_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lock_pool_action: Account<'info, LockedPool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8,
+        seeds = [
+            b"pool_count".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_count: Account<'info, Count>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + 32 + 8 + 1 + 8 + 1,
+        seeds = [
+            b"pool_entry".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref(),
+            &(pool_count.count + 1).to_le_bytes()
+        ],
+        bump
+    )]
+    pool_entry: Account<'info, PoolActionEntry>,
+
+    #[account(
+        init_if_needed, 
+        payer = staker, 
+        space = 8 + 8, 
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint= token_mint.key() == anchor_spl::token::spl_token::native_mint::ID @ ErrorCode::NotNativeMint,
+    )]
+    token_mint: Account<'info, Mint>,
+
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+pub struct ClaimWithdrawSol<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"withdraw_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    withdraw_pool_action: Account<'info, WithdrawRequest>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_count".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_count: Account<'info, Count>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + 32 + 8 + 1 + 8 + 1,
+        seeds = [
+            b"pool_entry".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref(),
+            &(pool_count.count + 1).to_le_bytes()
+        ],
+        bump
+    )]
+    pool_entry: Account<'info, PoolActionEntry>,
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = 8 + 32 + 32 + 8,
+        seeds = [
+            b"payout_config".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    payout_config: Account<'info, PayoutConfig>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    //Temporary wSOL account, closed to the staker within the claim
+    #[account(
+        init,
+        payer = staker,
+        token::mint = token_mint,
+        token::authority = current_staking_pool,
+        seeds = [
+            b"unwrap".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    unwrap_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint= token_mint.key() == anchor_spl::token::spl_token::native_mint::ID @ ErrorCode::NotNativeMint,
+    )]
+    token_mint: Account<'info, Mint>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+pub struct StakeFor<'info> {
+    #[account(mut)]
+    funder: Signer<'info>, 
+
+    /// CHECK: Seed of the positions staked for it, must also sign a locked gift
+    beneficiary: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        payer = funder, 
+        space = PoolAction::SPACE,
+        seeds = [
+            b"pool_action".as_ref(),
+            beneficiary.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        init_if_needed,
+        payer = funder, 
+        space = 8 + 4 + 4 + 4 + 4 + 8*4*LOT_GROWTH,
+        seeds = [
+            b"lock_pool_action".as_ref(),
+            beneficiary.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lock_pool_action: Account<'info, LockedPool>,
+
+    #[account(
+        init_if_needed,
+        payer = funder, 
+        space = 8 + 8,
+        seeds = [
+            b"pool_count".as_ref(),
+            beneficiary.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_count: Account<'info, Count>,
+
+    #[account(
+        init,
+        payer = funder,
+        space = 8 + 32 + 8 + 1 + 8 + 1,
+        seeds = [
+            b"pool_entry".as_ref(),
+            beneficiary.key().as_ref(),
+            token_mint.key().as_ref(),
+            &(pool_count.count + 1).to_le_bytes()
+        ],
+        bump
+    )]
+    pool_entry: Account<'info, PoolActionEntry>,
+
+    #[account(
+        init_if_needed, 
+        payer = funder, 
+        space = 8 + 8, 
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        init_if_needed,
+        payer = funder,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint= funder_associated_address.owner == funder.key(),
+        constraint= funder_associated_address.mint == token_mint.key(),
+    )]
+    funder_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+#[instruction(action_amount: u64)]
+pub struct RequestWithdraw<'info> {
+    #[account(mut)]
+    staker: Signer<'info>, 
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"lock_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lock_pool_action: Account<'info, LockedPool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8 + 8,
+        seeds = [
+            b"withdraw_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    withdraw_pool_action: Account<'info, WithdrawRequest>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_count".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_count: Account<'info, Count>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + 32 + 8 + 1 + 8 + 1,
+        seeds = [
+            b"pool_entry".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref(),
+            &(pool_count.count + 1).to_le_bytes()
+        ],
+        bump
+    )]
+    pool_entry: Account<'info, PoolActionEntry>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    token_mint: Account<'info, Mint>,
+
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+pub struct StakeLiquid<'info> {
+    #[account(mut)]
+    staker: Signer<'info>, 
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = PoolAction::SPACE,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8,
+        seeds = [
+            b"pool_count".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_count: Account<'info, Count>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + 32 + 8 + 1 + 8 + 1,
+        seeds = [
+            b"pool_entry".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref(),
+            &(pool_count.count + 1).to_le_bytes()
+        ],
+        bump
+    )]
+    pool_entry: Account<'info, PoolActionEntry>,
+
+    #[account(
+        init_if_needed, 
+        payer = staker, 
+        space = 8 + 8, 
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint= staker_associated_address.owner == staker.key(),
+        constraint= staker_associated_address.mint == token_mint.key(),
+    )]
+    staker_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        seeds = [
+            b"receipt_mint".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump,
+        mint::decimals = token_mint.decimals,
+        mint::authority = current_staking_pool,
+    )]
+    receipt_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = staker,
+    )]
+    staker_receipt_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+pub struct RequestWithdrawLiquid<'info> {
+    #[account(mut)]
+    staker: Signer<'info>, 
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8 + 8,
+        seeds = [
+            b"withdraw_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    withdraw_pool_action: Account<'info, WithdrawRequest>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8,
+        seeds = [
+            b"pool_count".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_count: Account<'info, Count>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + 32 + 8 + 1 + 8 + 1,
+        seeds = [
+            b"pool_entry".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref(),
+            &(pool_count.count + 1).to_le_bytes()
+        ],
+        bump
+    )]
+    pool_entry: Account<'info, PoolActionEntry>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        mut,
+        seeds = [
+            b"receipt_mint".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump,
+    )]
+    receipt_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint= staker_receipt_address.owner == staker.key(),
+        constraint= staker_receipt_address.mint == receipt_mint.key(),
+    )]
+    staker_receipt_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+pub struct UpdateInterest<'info> {
+    #[account(mut)]
+    admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed, 
+        payer = admin, 
+        space = 8 + 8, 
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+
+    #[account(
+        mut,
+        owner = *program_id,
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+    )]
+    admin_config: Account<'info, Config>,
+
+    token_mint: Account<'info, Mint>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+
+}
+
+#[derive(Accounts)]
+#[instruction(claim_amount: u64)]
+pub struct PerformWithdraw<'info>{
+    #[account(mut)]
+    staker: Signer<'info>, 
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8 + 8,
+        seeds = [
+            b"withdraw_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    withdraw_pool_action: Account<'info, WithdrawRequest>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8,
+        seeds = [
+            b"pool_count".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_count: Account<'info, Count>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + 32 + 8 + 1 + 8 + 1,
+        seeds = [
+            b"pool_entry".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref(),
+            &(pool_count.count + 1).to_le_bytes()
+        ],
+        bump
+    )]
+    pool_entry: Account<'info, PoolActionEntry>,
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = 8 + 32 + 32 + 8,
+        seeds = [
+            b"payout_config".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    payout_config: Account<'info, PayoutConfig>,
+
+    /// CHECK: Owner of the payout account, checked against the staker or the registered payout address
+    payout_owner: UncheckedAccount<'info>,
+
+    //Recreated for the claimer if it was closed after staking
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = token_mint,
+        associated_token::authority = payout_owner,
+    )]
+    staker_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+#[derive(Accounts)]
+pub struct UpdateConfig<'info>{
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        owner = *program_id,
+        payer = owner,
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+        space = 8 + 32
+    )]
+    pub admin_config: Account<'info, Config>,
+    
+    // Application level accounts 
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+
+}
+
+#[derive(Accounts)]
+#[instruction(withdraw_amount: u64)]
+pub struct WithdrawToken<'info>{
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        owner = *program_id,
+        payer = owner,
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+        space = 8 + 32
+    )]
+    pub admin_config: Account<'info, Config>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint= admin_associated_address.owner == owner.key(),
+        constraint= admin_associated_address.mint == token_mint.key(),
+    )]
+    admin_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed, 
+        payer = owner, 
+        space = 8 + 8, 
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    token_mint: Account<'info, Mint>,
+
+    associated_token_program: Program<'info, AssociatedToken>,
+    
+    // Application level accounts 
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(withdraw_amount: u64)]
+pub struct DepositToken<'info>{
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        owner = *program_id,
+        payer = owner,
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+        space = 8 + 32
+    )]
+    pub admin_config: Account<'info, Config>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint= admin_associated_address.owner == owner.key(),
+        constraint= admin_associated_address.mint == token_mint.key(),
+    )]
+    admin_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed, 
+        payer = owner, 
+        space = 8 + 8, 
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    token_mint: Account<'info, Mint>,
+
+    associated_token_program: Program<'info, AssociatedToken>,
+    
+    // Application level accounts 
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CheckSolvency<'info>{
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct SyncPool<'info>{
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+#[instruction(action_amount: u64)]
+pub struct EarlyWithdraw<'info>{
+    #[account(mut)]
+    staker: Signer<'info>, 
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"lock_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lock_pool_action: Account<'info, LockedPool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8 + 8,
+        seeds = [
+            b"withdraw_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    withdraw_pool_action: Account<'info, WithdrawRequest>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_count".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_count: Account<'info, Count>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + 32 + 8 + 1 + 8 + 1,
+        seeds = [
+            b"pool_entry".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref(),
+            &(pool_count.count + 1).to_le_bytes()
+        ],
+        bump
+    )]
+    pool_entry: Account<'info, PoolActionEntry>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        seeds = [
+            b"penalty_config".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    penalty_config: Account<'info, PenaltyConfig>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    //Ignored when penalties are redistributed
+    #[account(
+        mut,
+        constraint= penalty_config.penalty_destination == PENALTY_REDISTRIBUTE
+            || penalty_destination_account.key() == penalty_config.destination_account() @ ErrorCode::InvalidPenaltyConfig,
+        constraint= penalty_destination_account.mint == token_mint.key(),
+    )]
+    penalty_destination_account: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+pub struct UpdatePenaltyConfig<'info>{
+    #[account(mut)]
+    admin: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + 8 + 1 + 1 + 32 + 32 + 8,
+        seeds = [
+            b"penalty_config".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    penalty_config: Account<'info, PenaltyConfig>,
+
+    #[account(
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+    )]
+    admin_config: Account<'info, Config>,
+
+    #[account(
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    token_mint: Account<'info, Mint>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct DepositRewards<'info>{
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+    )]
+    admin_config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint= admin_associated_address.owner == owner.key(),
+        constraint= admin_associated_address.mint == token_mint.key(),
+    )]
+    admin_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRewardSchedule<'info>{
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+    )]
+    admin_config: Account<'info, Config>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct FundInterestBudget<'info>{
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+    )]
+    admin_config: Account<'info, Config>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint= admin_associated_address.owner == owner.key(),
+        constraint= admin_associated_address.mint == token_mint.key(),
+    )]
+    admin_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = 8 + 32 + 32 + 8,
+        seeds = [
+            b"payout_config".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    payout_config: Account<'info, PayoutConfig>,
+
+    /// CHECK: Owner of the payout account, checked against the staker or the registered payout address
+    payout_owner: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = token_mint,
+        associated_token::authority = payout_owner,
+    )]
+    staker_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+pub struct MergeLots<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"lock_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lock_pool_action: Account<'info, LockedPool>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    token_mint: Account<'info, Mint>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitPosition<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + std::mem::size_of::<LotPosition>(),
+        seeds = [
+            b"lot_position".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lot_position: AccountLoader<'info, LotPosition>,
+
+    token_mint: Account<'info, Mint>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePosition<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + std::mem::size_of::<LotPosition>(),
+        seeds = [
+            b"lot_position".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lot_position: AccountLoader<'info, LotPosition>,
+
+    #[account(
+        mut,
+        close = staker,
+        seeds = [
+            b"lock_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lock_pool_action: Account<'info, LockedPool>,
+
+    token_mint: Account<'info, Mint>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct StakePosition<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = PoolAction::SPACE,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"lot_position".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lot_position: AccountLoader<'info, LotPosition>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint= staker_associated_address.owner == staker.key(),
+        constraint= staker_associated_address.mint == token_mint.key(),
+    )]
+    staker_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RequestWithdrawPosition<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"lot_position".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    lot_position: AccountLoader<'info, LotPosition>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = 8 + 8 + 8,
+        seeds = [
+            b"withdraw_pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    withdraw_pool_action: Account<'info, WithdrawRequest>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    token_mint: Account<'info, Mint>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct StakeNftPosition<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        init_if_needed,
+        payer = staker, 
+        space = PoolAction::SPACE,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        init,
+        payer = staker,
+        mint::decimals = 0,
+        mint::authority = current_staking_pool,
+    )]
+    nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = staker,
+        associated_token::mint = nft_mint,
+        associated_token::authority = staker,
+    )]
+    staker_nft_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = 8 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 8,
+        seeds = [
+            b"nft_position".as_ref(),
+            nft_mint.key().as_ref()
+        ],
+        bump
+    )]
+    nft_position: Account<'info, NftPosition>,
+
+    #[account(
+        init_if_needed, 
+        payer = staker, 
+        space = 8 + 8, 
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint= staker_associated_address.owner == staker.key(),
+        constraint= staker_associated_address.mint == token_mint.key(),
+    )]
+    staker_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[derive(Accounts)]
+pub struct TransferPosition<'info>{
+    #[account(mut)]
+    holder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"nft_position".as_ref(),
+            nft_position.nft_mint.as_ref()
+        ],
         bump
     )]
-    current_staking_pool: Account<'info, StakePool>,
+    nft_position: Account<'info, NftPosition>,
+
+    #[account(
+        constraint= holder_nft_address.owner == holder.key(),
+        constraint= holder_nft_address.mint == nft_position.nft_mint @ ErrorCode::NotPositionHolder,
+        constraint= holder_nft_address.amount == 1 @ ErrorCode::NotPositionHolder,
+    )]
+    holder_nft_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_action".as_ref(),
+            nft_position.owner.as_ref(),
+            nft_position.token_mint.as_ref()
+        ],
+        bump
+    )]
+    previous_pool_action: Account<'info, PoolAction>,
 
     #[account(
         init_if_needed,
-        payer = staker, 
-        space = 8 + 8 + 8 + 8,
+        payer = holder, 
+        space = PoolAction::SPACE,
+        seeds = [
+            b"pool_action".as_ref(),
+            holder.key().as_ref(),
+            nft_position.token_mint.as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct NftPositionAction<'info>{
+    #[account(mut)]
+    holder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"nft_position".as_ref(),
+            nft_position.nft_mint.as_ref()
+        ],
+        bump,
+        constraint= nft_position.owner == holder.key() @ ErrorCode::NotPositionHolder,
+        constraint= nft_position.token_mint == token_mint.key(),
+    )]
+    nft_position: Account<'info, NftPosition>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_action".as_ref(),
+            holder.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: Account<'info, PoolAction>,
+
+    #[account(
+        constraint= holder_nft_address.owner == holder.key(),
+        constraint= holder_nft_address.mint == nft_position.nft_mint @ ErrorCode::NotPositionHolder,
+        constraint= holder_nft_address.amount == 1 @ ErrorCode::NotPositionHolder,
+    )]
+    holder_nft_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            b"token_interest".as_ref(), 
+            token_mint.key().as_ref()
+        ], 
+        bump
+    )]
+    token_interest: Account<'info, InterestRate>, 
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = current_staking_pool,
+    )]
+    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint= holder_associated_address.owner == holder.key(),
+        constraint= holder_associated_address.mint == token_mint.key(),
+    )]
+    holder_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseEntry<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    /// CHECK: Seeded with the index in either width, checked in close_entry
+    #[account(
+        mut,
+        owner = crate::ID
+    )]
+    pool_entry: UncheckedAccount<'info>,
+
+    token_mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePosition<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        mut,
+        close = staker,
         seeds = [
             b"pool_action".as_ref(),
             staker.key().as_ref(),
@@ -345,10 +4433,9 @@ pub struct PerformAction<'info> {
     )]
     pool_action: Account<'info, PoolAction>,
 
+    /// CHECK: The staker's lots, closed when it exists
     #[account(
-        init_if_needed,
-        payer = staker, 
-        space = 8 + 4 + 8*2*10, //8*2*10
+        mut,
         seeds = [
             b"lock_pool_action".as_ref(),
             staker.key().as_ref(),
@@ -356,13 +4443,11 @@ pub struct PerformAction<'info> {
         ],
         bump
     )]
-    lock_pool_action: Account<'info, LockedPool>,
+    lock_pool_action: UncheckedAccount<'info>,
 
-    
     #[account(
-        init_if_needed,
-        payer = staker, 
-        space = 8 + 8 + 8,
+        mut,
+        close = staker,
         seeds = [
             b"withdraw_pool_action".as_ref(),
             staker.key().as_ref(),
@@ -372,89 +4457,160 @@ pub struct PerformAction<'info> {
     )]
     withdraw_pool_action: Account<'info, WithdrawRequest>,
 
+    /// CHECK: The staker's zero-copy position, closed when it exists
     #[account(
-        init_if_needed,
-        payer = staker,
-        space = 8 + 32 + 8 + 1 + 8 + 1,
+        mut,
         seeds = [
-            b"pool_entry".as_ref(),
+            b"lot_position".as_ref(),
             staker.key().as_ref(),
-            token_mint.key().as_ref(),
-            &[count]
+            token_mint.key().as_ref()
         ],
         bump
     )]
-    pool_entry: Account<'info, PoolActionEntry>,
+    lot_position: UncheckedAccount<'info>,
+
+    token_mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct SetPayoutAddress<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
 
     #[account(
-        init_if_needed,
-        payer = staker, 
-        space = 8 + 4,
         seeds = [
-            b"pool_count".as_ref(),
-            staker.key().as_ref(),
+            b"stake_pool".as_ref(),
             token_mint.key().as_ref()
         ],
         bump
     )]
-    pool_count: Account<'info, Count>,
+    current_staking_pool: Account<'info, StakePool>,
 
     #[account(
-        init_if_needed, 
-        payer = staker, 
-        space = 8 + 8, 
+        init_if_needed,
+        payer = staker,
+        space = 8 + 32 + 32 + 8,
         seeds = [
-            b"token_interest".as_ref(), 
+            b"payout_config".as_ref(),
+            staker.key().as_ref(),
             token_mint.key().as_ref()
-        ], 
+        ],
         bump
     )]
-    token_interest: Account<'info, InterestRate>, 
+    payout_config: Account<'info, PayoutConfig>,
+
+    /// CHECK: Only used to derive the canonical payout account
+    payout_owner: UncheckedAccount<'info>,
 
+    //Only canonical accounts, so claims can recreate them
     #[account(
-        init_if_needed,
-        payer = staker,
         associated_token::mint = token_mint,
-        associated_token::authority = current_staking_pool,
+        associated_token::authority = payout_owner,
     )]
-    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+    payout_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPayoutAddress<'info>{
+    staker: Signer<'info>,
 
     #[account(
-         mut,
-        constraint= staker_associated_address.owner == staker.key(),
-        constraint= staker_associated_address.mint == token_mint.key(),
+        mut,
+        seeds = [
+            b"payout_config".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
     )]
-    staker_associated_address: Box<Account<'info, TokenAccount>>,
+    payout_config: Account<'info, PayoutConfig>,
 
     token_mint: Account<'info, Mint>,
+}
 
-    associated_token_program: Program<'info, AssociatedToken>,
+#[derive(Accounts)]
+pub struct UpdatePoolConfig<'info>{
+    #[account(mut)]
+    admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+    )]
+    admin_config: Account<'info, Config>,
+
+    token_mint: Account<'info, Mint>,
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
-    rent: Sysvar<'info, Rent>
+    rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateInterest<'info> {
+pub struct CreatePool<'info>{
     #[account(mut)]
     admin: Signer<'info>,
 
     #[account(
-        init_if_needed, 
-        payer = admin, 
-        space = 8 + 8, 
+        init,
+        payer = admin,
+        space = StakePool::SPACE,
         seeds = [
-            b"token_interest".as_ref(), 
+            b"stake_pool".as_ref(),
             token_mint.key().as_ref()
-        ], 
+        ],
         bump
     )]
-    token_interest: Account<'info, InterestRate>, 
+    current_staking_pool: Account<'info, StakePool>,
+
+    #[account(
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+    )]
+    admin_config: Account<'info, Config>,
+
+    /// CHECK: Owner is checked here so other token programs get a clear error, deserialized in the handler
+    #[account(owner = anchor_spl::token::ID @ ErrorCode::UnsupportedTokenProgram)]
+    token_mint: UncheckedAccount<'info>,
+
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>
+}
 
+#[derive(Accounts)]
+pub struct MigratePool<'info>{
+    #[account(mut)]
+    admin: Signer<'info>,
 
+    /// CHECK: Still in the old layout, only grown here
     #[account(
         mut,
-        owner = program_id.clone(),
+        owner = crate::ID,
+        seeds = [
+            b"stake_pool".as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    current_staking_pool: UncheckedAccount<'info>,
+
+    #[account(
         seeds= [
             b"admin_config".as_ref(),
         ],
@@ -463,23 +4619,16 @@ pub struct UpdateInterest<'info> {
     admin_config: Account<'info, Config>,
 
     token_mint: Account<'info, Mint>,
-    associated_token_program: Program<'info, AssociatedToken>,
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
-    rent: Sysvar<'info, Rent>
-
 }
 
 #[derive(Accounts)]
-#[instruction(claim_amount: u64, count: u8)]
-pub struct PerformWithdraw<'info>{
+pub struct MigratePoolAction<'info>{
     #[account(mut)]
-    staker: Signer<'info>, 
+    staker: Signer<'info>,
 
     #[account(
-        init_if_needed,
-        payer = staker,
-        space = 8 + 32 + 8,
+        mut,
         seeds = [
             b"stake_pool".as_ref(),
             token_mint.key().as_ref()
@@ -488,227 +4637,659 @@ pub struct PerformWithdraw<'info>{
     )]
     current_staking_pool: Account<'info, StakePool>,
 
+    /// CHECK: Still in the old layout, only grown here
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [
+            b"pool_action".as_ref(),
+            staker.key().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pool_action: UncheckedAccount<'info>,
+
+    token_mint: Account<'info, Mint>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePoolCount<'info>{
+    #[account(mut)]
+    staker: Signer<'info>,
+
+    /// CHECK: Still in the old layout, widened here
     #[account(
-        init_if_needed,
-        payer = staker, 
-        space = 8 + 8 + 8,
+        mut,
+        owner = crate::ID,
         seeds = [
-            b"withdraw_pool_action".as_ref(),
+            b"pool_count".as_ref(),
             staker.key().as_ref(),
             token_mint.key().as_ref()
         ],
         bump
     )]
-    withdraw_pool_action: Account<'info, WithdrawRequest>,
+    pool_count: UncheckedAccount<'info>,
+
+    token_mint: Account<'info, Mint>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetBasketMint<'info>{
+    #[account(mut)]
+    admin: Signer<'info>,
 
     #[account(
         init_if_needed,
-        payer = staker,
-        space = 8 + 32 + 8 + 1 + 8 + 1,
+        payer = admin,
+        space = BasketPool::SPACE,
         seeds = [
-            b"pool_entry".as_ref(),
-            staker.key().as_ref(),
-            token_mint.key().as_ref(),
-            &[count]
+            b"basket_pool".as_ref()
         ],
         bump
     )]
-    pool_entry: Account<'info, PoolActionEntry>,
+    basket_pool: Account<'info, BasketPool>,
 
     #[account(
         init_if_needed,
-        payer = staker, 
-        space = 8 + 4,
+        payer = admin,
+        space = 8 + 32 + 1 + 8 + 8 + 1,
         seeds = [
-            b"pool_count".as_ref(),
-            staker.key().as_ref(),
+            b"basket_mint".as_ref(),
             token_mint.key().as_ref()
         ],
         bump
     )]
-    pool_count: Account<'info, Count>,
+    basket_mint: Account<'info, BasketMint>,
 
     #[account(
         init_if_needed,
-        payer = staker,
+        payer = admin,
         associated_token::mint = token_mint,
-        associated_token::authority = current_staking_pool,
+        associated_token::authority = basket_pool,
     )]
-    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+    basket_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
-         mut,
-        constraint= staker_associated_address.owner == staker.key(),
-        constraint= staker_associated_address.mint == token_mint.key(),
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
     )]
-    staker_associated_address: Box<Account<'info, TokenAccount>>,
+    admin_config: Account<'info, Config>,
 
     token_mint: Account<'info, Mint>,
+
     associated_token_program: Program<'info, AssociatedToken>,
     system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
-    rent: Sysvar<'info, Rent>
+    rent: Sysvar<'info, Rent>,
 }
+
 #[derive(Accounts)]
-pub struct UpdateConfig<'info>{
-    
+pub struct UpdateBasketInterest<'info>{
     #[account(mut)]
-    pub owner: Signer<'info>,
+    admin: Signer<'info>,
 
     #[account(
         init_if_needed,
-        owner = program_id.clone(),
-        payer = owner,
+        payer = admin,
+        space = BasketPool::SPACE,
+        seeds = [
+            b"basket_pool".as_ref()
+        ],
+        bump
+    )]
+    basket_pool: Account<'info, BasketPool>,
+
+    #[account(
         seeds= [
             b"admin_config".as_ref(),
         ],
         bump,
-        space = 8 + 32
     )]
-    pub admin_config: Account<'info, Config>,
-    
-    // Application level accounts 
+    admin_config: Account<'info, Config>,
+
     system_program: Program<'info, System>,
     rent: Sysvar<'info, Rent>,
-
 }
 
 #[derive(Accounts)]
-#[instruction(withdraw_amount: u64)]
-pub struct WithdrawToken<'info>{
+pub struct FundBasketInterest<'info>{
     #[account(mut)]
-    pub owner: Signer<'info>,
+    admin: Signer<'info>,
 
     #[account(
-        init_if_needed,
-        payer = owner,
-        space = 8 + 32 + 8,
+        mut,
         seeds = [
-            b"stake_pool".as_ref(),
-            token_mint.key().as_ref()
+            b"basket_pool".as_ref()
         ],
         bump
     )]
-    current_staking_pool: Account<'info, StakePool>,
+    basket_pool: Account<'info, BasketPool>,
 
+    //Only exists for allowlisted mints
     #[account(
-        init_if_needed,
-        owner = program_id.clone(),
-        payer = owner,
-        seeds= [
-            b"admin_config".as_ref(),
+        seeds = [
+            b"basket_mint".as_ref(),
+            token_mint.key().as_ref()
         ],
-        bump,
-        space = 8 + 32
+        bump
     )]
-    pub admin_config: Account<'info, Config>,
+    basket_mint: Account<'info, BasketMint>,
 
     #[account(
-        init_if_needed,
-        payer = owner,
+        mut,
         associated_token::mint = token_mint,
-        associated_token::authority = current_staking_pool,
+        associated_token::authority = basket_pool,
     )]
-    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+    basket_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
-        constraint= admin_associated_address.owner == owner.key(),
+        constraint= admin_associated_address.owner == admin.key(),
         constraint= admin_associated_address.mint == token_mint.key(),
     )]
     admin_associated_address: Box<Account<'info, TokenAccount>>,
 
-    token_mint: Account<'info, Mint>,
+    #[account(
+        seeds= [
+            b"admin_config".as_ref(),
+        ],
+        bump,
+    )]
+    admin_config: Account<'info, Config>,
 
-    associated_token_program: Program<'info, AssociatedToken>,
-    
-    // Application level accounts 
-    system_program: Program<'info, System>,
+    token_mint: Account<'info, Mint>,
     token_program: Program<'info, Token>,
-    rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-#[instruction(withdraw_amount: u64)]
-pub struct DepositToken<'info>{
+pub struct BasketAction<'info>{
     #[account(mut)]
-    pub owner: Signer<'info>,
+    staker: Signer<'info>,
 
     #[account(
-        init_if_needed,
-        payer = owner,
-        space = 8 + 32 + 8,
+        mut,
         seeds = [
-            b"stake_pool".as_ref(),
+            b"basket_pool".as_ref()
+        ],
+        bump
+    )]
+    basket_pool: Account<'info, BasketPool>,
+
+    //Only exists for allowlisted mints
+    #[account(
+        mut,
+        seeds = [
+            b"basket_mint".as_ref(),
             token_mint.key().as_ref()
         ],
         bump
     )]
-    current_staking_pool: Account<'info, StakePool>,
+    basket_mint: Account<'info, BasketMint>,
 
     #[account(
         init_if_needed,
-        owner = program_id.clone(),
-        payer = owner,
-        seeds= [
-            b"admin_config".as_ref(),
+        payer = staker,
+        space = 8 + 32 + 8 + 8 + 16,
+        seeds = [
+            b"basket_position".as_ref(),
+            staker.key().as_ref()
         ],
-        bump,
-        space = 8 + 32
+        bump
     )]
-    pub admin_config: Account<'info, Config>,
+    basket_position: Account<'info, BasketPosition>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = basket_pool,
+    )]
+    basket_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
         init_if_needed,
-        payer = owner,
+        payer = staker,
         associated_token::mint = token_mint,
-        associated_token::authority = current_staking_pool,
+        associated_token::authority = staker,
     )]
-    staking_vault_associated_address: Box<Account<'info, TokenAccount>>,
+    staker_associated_address: Box<Account<'info, TokenAccount>>,
+
+    token_mint: Account<'info, Mint>,
+
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>
+}
+
+#[account]
+#[derive(Default)]
+pub struct Config{
+    admin: Pubkey
+}
+
+#[account]
+#[derive(Default)]
+pub struct StakePool {
+    token_mint: Pubkey,
+    token_amount: u64,
+    total_staked: u64,
+    pending_withdraw: u64,
+    accrued_interest: u64,
+    last_accrual_time: i64,
+    coverage_ratio: u64, // basis points of liabilities held in the vault
+    min_coverage_ratio: u64, // basis points, 0 disables the circuit breaker
+    circuit_breaker: bool,
+    surplus: u64,
+    shortfall: u64,
+    last_sync_time: i64,
+    undistributed_rewards: u64, // held back while nobody is staked
+    total_weight: u64,
+    reward_per_share: u128, // scaled by REWARD_PRECISION
+    reward_liability: u64, // distributed but not yet claimed
+    total_penalty_distributed: u64,
+    total_rewards_deposited: u64,
+    reward_rate: u128, // streamed per second, scaled by REWARD_PRECISION
+    reward_start: i64,
+    reward_end: i64,
+    last_reward_update: i64,
+    stream_remaining: u64, // deposited but not streamed yet
+    interest_budget: u64, // funded for interest, not accrued yet
+    interest_paused: bool, // budget exhausted, no interest accrues
+    interest_paused_at: i64, // start of the current pause
+    interest_paused_seconds: i64, // total length of the pauses that ended
+    interest_unbudgeted: bool, // migrated pool, accrues without a budget until first funded
+    liquid_staked: u64, // principal and compounded interest backing receipt tokens
+    payout_change_delay: i64, // seconds before a changed payout address applies
+    transfer_tolerance: u64, // bps a stake transfer may fall short by
+    min_stake: u64,
+    max_stake_per_user: u64, // 0 for no limit
+    pool_capacity: u64, // 0 for no limit
+    decimals: u8, // of the mint, recorded at creation and passed to transfer_checked
+}
+
+impl StakePool {
+    const SPACE: usize = 8 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 8 + 8 + 16 + 8 + 8 + 8 + 16 + 8 + 8 + 8 + 8 + 8 + 1 + 8 + 8 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 1;
+
+    //Accrue interest on the total staked principal since the last accrual, paid from the interest budget
+    fn accrue_interest(&mut self, interest: u8, current_time: i64) {
+        if self.last_accrual_time != 0 {
+            let elapsed = current_time - self.last_accrual_time;
+            let owed_interest = calculate_interest(self.total_staked, interest, elapsed);
+            let accrued_interest = if self.interest_unbudgeted {
+                owed_interest
+            } else {
+                owed_interest.min(self.interest_budget)
+            };
+            self.interest_budget = self.interest_budget.saturating_sub(accrued_interest);
+
+            //Interest on liquid stake compounds into the receipt exchange rate
+            let liquid_interest = if self.total_staked == 0 {
+                0
+            } else {
+                (accrued_interest as u128 * self.liquid_staked as u128 / self.total_staked as u128) as u64
+            };
+            self.liquid_staked += liquid_interest;
+            self.total_staked += liquid_interest;
+            self.accrued_interest += accrued_interest - liquid_interest;
+
+            //Budget ran out, stop accrual from the moment it did
+            if accrued_interest < owed_interest && !self.interest_paused {
+                self.interest_paused = true;
+                self.interest_paused_at = self.last_accrual_time + (elapsed as u128 * accrued_interest as u128 / owed_interest as u128) as i64;
+            }
+        }
+        self.last_accrual_time = current_time;
+    }
+
+    //Total seconds the interest budget has been paused up to `current_time`
+    fn paused_seconds(&self, current_time: i64) -> i64 {
+        let current_pause = if self.interest_paused {
+            (current_time - self.interest_paused_at).max(0)
+        } else {
+            0
+        };
+        self.interest_paused_seconds + current_pause
+    }
+
+    //Seconds that earned interest up to `current_time`, stands still while paused
+    fn interest_clock(&self, current_time: i64) -> i64 {
+        current_time - self.paused_seconds(current_time)
+    }
+
+    //Seconds since `interest_start`, an earlier interest_clock reading, that earn interest
+    fn interest_elapsed(&self, interest_start: i64, current_time: i64) -> i64 {
+        self.interest_clock(current_time) - interest_start
+    }
+
+    //Seconds until the interest budget runs out at the given rate
+    fn interest_runway(&self, interest: u8) -> i64 {
+        let interest_per_year = self.total_staked as u128 * interest as u128 / 100;
+        if interest_per_year == 0 {
+            return i64::MAX;
+        }
+        i64::try_from(self.interest_budget as u128 * 31536000 / interest_per_year).unwrap_or(i64::MAX)
+    }
+
+    //Principal left the lots and settled interest was paid or folded back in
+    fn book_withdraw_request(&mut self, locked_before: u64, locked_after: u64, realized_interest: u64, requested_amount: u64) {
+        self.total_staked = (self.total_staked + locked_after).saturating_sub(locked_before);
+        self.accrued_interest = self.accrued_interest.saturating_sub(realized_interest);
+        self.pending_withdraw += requested_amount;
+    }
+
+    //A withdraw request was paid out, requests made before migrate_pool were booked as staked principal
+    fn book_claim(&mut self, claim_amount: u64) {
+        let requested_amount = claim_amount.min(self.pending_withdraw);
+        self.pending_withdraw -= requested_amount;
+        self.total_staked = self.total_staked.saturating_sub(claim_amount - requested_amount);
+    }
+
+    //Settle a staker's rewards and move their weight to `token_amount`
+    fn set_weight(&mut self, pool_action: &mut PoolAction, token_amount: u64, current_time: i64) {
+        self.update_rewards(current_time);
+
+        let accumulated = pool_action.token_amount as u128 * self.reward_per_share / REWARD_PRECISION;
+        pool_action.pending_reward += accumulated.saturating_sub(pool_action.reward_debt) as u64;
+
+        self.total_weight = (self.total_weight + token_amount).saturating_sub(pool_action.token_amount);
+        pool_action.token_amount = token_amount;
+        pool_action.reward_debt = token_amount as u128 * self.reward_per_share / REWARD_PRECISION;
+    }
+
+    //Credit `amount` and anything held back to stakers pro-rata by weight
+    //Held back while nobody is staked, returns the amount distributed
+    fn distribute_rewards(&mut self, amount: u64) -> u64 {
+        self.undistributed_rewards += amount;
+        if self.total_weight == 0 {
+            return 0;
+        }
+
+        let distributed_amount = self.undistributed_rewards;
+        self.reward_per_share += distributed_amount as u128 * REWARD_PRECISION / self.total_weight as u128;
+        self.reward_liability += distributed_amount;
+        self.undistributed_rewards = 0;
+        distributed_amount
+    }
+
+    //Distribute the part of the reward stream that elapsed since the last update
+    fn update_rewards(&mut self, current_time: i64) {
+        let stream_from = self.last_reward_update.max(self.reward_start);
+        let stream_until = current_time.min(self.reward_end);
+        let mut streamed_amount = 0;
+        if stream_until > stream_from {
+            streamed_amount = (self.reward_rate * (stream_until - stream_from) as u128 / REWARD_PRECISION) as u64;
+            streamed_amount = streamed_amount.min(self.stream_remaining);
+        }
+        //Rounding dust is released once the stream ends
+        if current_time >= self.reward_end {
+            streamed_amount = self.stream_remaining;
+        }
+        if streamed_amount > 0 {
+            self.stream_remaining -= streamed_amount;
+            self.distribute_rewards(streamed_amount);
+        }
+        self.last_reward_update = current_time;
+    }
+
+    //Stream `amount` plus what is left of the current stream evenly over `stream_duration`
+    fn start_stream(&mut self, amount: u64, stream_duration: i64, current_time: i64) {
+        self.update_rewards(current_time);
+        self.stream_remaining += amount;
+        self.reward_start = current_time;
+        self.reward_end = current_time + stream_duration;
+        self.update_reward_rate(current_time);
+    }
+
+    //Spread what is left of the stream over the rest of the schedule, call update_rewards first
+    fn update_reward_rate(&mut self, current_time: i64) {
+        let stream_from = current_time.max(self.reward_start);
+        self.reward_rate = if self.reward_end > stream_from {
+            self.stream_remaining as u128 * REWARD_PRECISION / (self.reward_end - stream_from) as u128
+        } else {
+            0
+        };
+    }
+
+    //Receipts minted for staking `amount` at the current exchange rate
+    fn receipts_for(&self, amount: u64, receipt_supply: u64) -> u64 {
+        if receipt_supply == 0 || self.liquid_staked == 0 {
+            return amount;
+        }
+        (amount as u128 * receipt_supply as u128 / self.liquid_staked as u128) as u64
+    }
+
+    //Tokens owed for burning `receipt_amount` at the current exchange rate
+    fn receipt_value(&self, receipt_amount: u64, receipt_supply: u64) -> u64 {
+        if receipt_supply == 0 {
+            return 0;
+        }
+        (receipt_amount as u128 * self.liquid_staked as u128 / receipt_supply as u128) as u64
+    }
+
+    //Stake the pool still accepts before reaching its capacity
+    fn remaining_capacity(&self) -> u64 {
+        if self.pool_capacity == 0 {
+            return u64::MAX;
+        }
+        self.pool_capacity.saturating_sub(self.total_staked)
+    }
+
+    //Reject stakes outside the admin limits, `user_staked` is what the staker already holds in the pool
+    fn check_stake_limits(&self, action_amount: u64, user_staked: u64) -> Result<()> {
+        require!(action_amount > 0 && action_amount >= self.min_stake, ErrorCode::StakeBelowMinimum);
+        require!(self.max_stake_per_user == 0 || user_staked + action_amount <= self.max_stake_per_user, ErrorCode::StakeAboveUserMaximum);
+        require!(action_amount <= self.remaining_capacity(), ErrorCode::PoolCapacityExceeded);
+        Ok(())
+    }
+
+    //Amount that actually reached the vault, rejects transfers short by more than the tolerance
+    fn received_amount(&self, action_amount: u64, vault_before: u64, vault_after: u64) -> Result<u64> {
+        let received_amount = vault_after.saturating_sub(vault_before);
+        let shortfall = action_amount.saturating_sub(received_amount);
+        require!(shortfall as u128 * 10000 <= action_amount as u128 * self.transfer_tolerance as u128, ErrorCode::TransferShortfall);
+        Ok(received_amount)
+    }
+
+    //Vault tokens not owed to anybody, available to fund a reward schedule
+    fn unallocated_amount(&self, vault_amount: u64) -> u64 {
+        vault_amount.saturating_sub(self.liabilities() + self.interest_budget)
+    }
+
+    fn liabilities(&self) -> u64 {
+        self.total_staked + self.accrued_interest + self.pending_withdraw + self.reward_liability + self.undistributed_rewards + self.stream_remaining
+    }
+
+    fn update_coverage(&mut self, vault_amount: u64) {
+        let liabilities = self.liabilities();
+        self.coverage_ratio = if liabilities == 0 {
+            u64::MAX
+        } else {
+            u64::try_from(vault_amount as u128 * 10000 / liabilities as u128).unwrap_or(u64::MAX)
+        };
+    }
+
+    fn is_undercovered(&self) -> bool {
+        self.min_coverage_ratio > 0 && self.coverage_ratio < self.min_coverage_ratio
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct PoolAction{
+    token_amount: u64,
+    start_time: i64,
+    update_time: i64,
+    reward_debt: u128,
+    pending_reward: u64,
+    position_staked: u64, // NFT principal, counted against max_stake_per_user
+}
+
+impl PoolAction {
+    const SPACE: usize = 8 + 8 + 8 + 8 + 16 + 8 + 8;
+
+    //Everything this staker has staked in the pool, lots and positions alike
+    fn staked(&self) -> u64 {
+        self.token_amount + self.position_staked
+    }
+
+    fn touch(&mut self, current_time: i64) {
+        if self.start_time == 0{
+            self.start_time = current_time; //Set the start time for the first time 
+        }
+        self.update_time = current_time; //Set the update time to current time
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct LockedPool{
+    locked_amount: Vec<u64>,  // 100000
+    locked_start_time: Vec<i64>, // 23456666
+    locked_until: Vec<i64>, // set by stake_for, empty on accounts created before it
+    locked_interest_start: Vec<i64> // StakePool::interest_clock when each lot started earning
+}
+
+impl LockedPool {
+    fn space(lots: usize) -> usize {
+        8 + 4 + 4 + 4 + 4 + 8*4*lots
+    }
+
+    fn has_free_lot(&self) -> bool {
+        self.locked_amount.iter().zip(self.locked_start_time.iter()).any(|(amount, start_time)| *amount == 0 && *start_time == 0)
+    }
+
+    //Lots the account must hold after the next stake, with room for the lock of every lot
+    fn lots_after_stake(&self) -> usize {
+        self.locked_amount.len() + if self.has_free_lot() { 0 } else { 1 }
+    }
+
+    //No withdraw of lot `n` before this time
+    fn lock_of(&self, n: usize) -> i64 {
+        self.locked_until.get(n).copied().unwrap_or(0)
+    }
+
+    //Lots staked before pauses were tracked started earning at their start time
+    fn interest_start_of(&self, n: usize) -> i64 {
+        self.locked_interest_start.get(n).copied().unwrap_or(self.locked_start_time[n])
+    }
+
+    //Give every lot a lock and interest start, the account must already have room for them
+    fn pad_lots(&mut self) {
+        let lots = self.locked_amount.len();
+        self.locked_until.resize(lots, 0);
+        for n in self.locked_interest_start.len()..lots {
+            self.locked_interest_start.push(self.locked_start_time[n]);
+        }
+    }
+}
 
-    #[account(
-        mut,
-        constraint= admin_associated_address.owner == owner.key(),
-        constraint= admin_associated_address.mint == token_mint.key(),
-    )]
-    admin_associated_address: Box<Account<'info, TokenAccount>>,
+#[zero_copy]
+#[derive(Default)]
+pub struct Lot{
+    amount: u64,
+    start_time: i64,
+    interest_start: i64, // StakePool::interest_clock when the lot started earning
+    tier: u8,
+    flags: u8,
+}
 
-    token_mint: Account<'info, Mint>,
+//Fixed capacity position, loaded without Borsh deserialization
+#[account(zero_copy)]
+pub struct LotPosition{
+    owner: Pubkey,
+    lots: [Lot; 128], // POSITION_LOTS
+}
 
-    associated_token_program: Program<'info, AssociatedToken>,
-    
-    // Application level accounts 
-    system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
-    rent: Sysvar<'info, Rent>,
+//Single-lot position owned by whoever holds its NFT
+#[account]
+#[derive(Default)]
+pub struct NftPosition{
+    owner: Pubkey,
+    nft_mint: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    start_time: i64,
+    interest_start: i64, // StakePool::interest_clock when the position started earning
+    requested_amount: u64,
+    requested_time: i64,
 }
 
+//Where claim_withdraw pays out, with a delayed pending change
 #[account]
 #[derive(Default)]
-pub struct Config{
-    admin: Pubkey
+pub struct PayoutConfig{
+    payout_address: Pubkey,
+    pending_payout_address: Pubkey,
+    payout_effective_time: i64,
+}
+
+impl PayoutConfig {
+    //Promote the pending address once its delay has passed
+    fn apply_pending(&mut self, current_time: i64) {
+        if self.pending_payout_address != Pubkey::default() && self.payout_effective_time <= current_time {
+            self.payout_address = self.pending_payout_address;
+            self.pending_payout_address = Pubkey::default();
+        }
+    }
 }
 
+//Pool staking several stablecoins, amounts in BASKET_DECIMALS
 #[account]
 #[derive(Default)]
-pub struct StakePool {
-    token_mint: Pubkey,
-    token_amount: u64
+pub struct BasketPool{
+    interest: u8,
+    total_staked: u64,
+    interest_budget: u64, // funded for interest, not accrued yet
+    interest_index: u128, // interest per basket unit, scaled by REWARD_PRECISION
+    last_accrual_time: i64,
+}
+
+impl BasketPool {
+    const SPACE: usize = 8 + 1 + 8 + 8 + 16 + 8;
+
+    //Accrue interest on the total staked since the last accrual into the index, paid from the budget
+    fn accrue(&mut self, current_time: i64) {
+        if self.last_accrual_time != 0 && self.total_staked > 0 {
+            let owed_interest = calculate_interest(self.total_staked, self.interest, current_time - self.last_accrual_time);
+            let accrued_interest = owed_interest.min(self.interest_budget);
+            self.interest_budget -= accrued_interest;
+            self.interest_index += accrued_interest as u128 * REWARD_PRECISION / self.total_staked as u128;
+        }
+        self.last_accrual_time = current_time;
+    }
 }
 
+//Allowlisted basket stablecoin with its vault balance and cap in the basket unit
 #[account]
 #[derive(Default)]
-pub struct PoolAction{
-    token_amount: u64,
-    start_time: i64,
-    update_time: i64,
+pub struct BasketMint{
+    token_mint: Pubkey,
+    decimals: u8,
+    cap: u64,
+    balance: u64,
+    enabled: bool,
 }
 
 #[account]
 #[derive(Default)]
-pub struct LockedPool{
-    locked_amount: Vec<u64>,  // 100000
-    locked_start_time: Vec<i64> // 23456666
+pub struct BasketPosition{
+    owner: Pubkey,
+    amount: u64,
+    accrued_interest: u64,
+    interest_index: u128, // pool index at the last settle
+}
+
+impl BasketPosition {
+    //Move the interest earned since the last settle into accrued_interest
+    fn settle(&mut self, interest_index: u128) {
+        self.accrued_interest += (self.amount as u128 * (interest_index - self.interest_index) / REWARD_PRECISION) as u64;
+        self.interest_index = interest_index;
+    }
 }
 
 #[account]
@@ -734,10 +5315,623 @@ pub struct InterestRate{
     interest: u8,
 }
 
+#[account]
+#[derive(Default)]
+pub struct PenaltyConfig{
+    penalty_rate: u64, // basis points of principal at the start of the lock
+    forfeit_interest: bool,
+    penalty_destination: u8,
+    treasury: Pubkey,
+    insurance_fund: Pubkey,
+    penalty_floor: u64, // base units, least penalty charged inside the lock
+}
+
+impl PenaltyConfig {
+    fn destination_account(&self) -> Pubkey {
+        match self.penalty_destination {
+            PENALTY_TREASURY => self.treasury,
+            PENALTY_INSURANCE => self.insurance_fund,
+            _ => Pubkey::default(),
+        }
+    }
+}
+
 #[account]
 #[derive(Default)]
 pub struct Count{
-    count: u8,
+    count: u64,
+}
+
+#[event]
+pub struct Staked{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    interest_rate: u8,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct WithdrawRequested{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    principal_amount: u64,
+    interest_amount: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct WithdrawClaimed{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    remaining_requested: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct InterestRateUpdated{
+    admin: Pubkey,
+    token_mint: Pubkey,
+    old_interest: u8,
+    new_interest: u8,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct AdminChanged{
+    old_admin: Pubkey,
+    new_admin: Pubkey,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct PoolCreated{
+    admin: Pubkey,
+    token_mint: Pubkey,
+    decimals: u8,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct TokensRescued{
+    admin: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct TokensDeposited{
+    admin: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct EarlyWithdrawn{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    interest_amount: u64,
+    penalty_amount: u64,
+    penalty_destination: u8,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct RewardsDistributed{
+    token_mint: Pubkey,
+    amount: u64,
+    source: u8,
+    reward_per_share: u128,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct RewardsDeposited{
+    admin: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    reward_end: i64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct RewardScheduleUpdated{
+    token_mint: Pubkey,
+    reward_start: i64,
+    reward_end: i64,
+    stream_remaining: u64,
+    reward_rate: u128,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct StakeCapacity{
+    token_mint: Pubkey,
+    min_stake: u64,
+    max_stake_per_user: u64,
+    pool_capacity: u64,
+    total_staked: u64,
+    remaining_capacity: u64, // u64::MAX without a pool capacity
+    time_stamp: i64,
+}
+
+#[event]
+pub struct InterestRunway{
+    token_mint: Pubkey,
+    interest_budget: u64,
+    interest: u8,
+    total_staked: u64,
+    runway: i64, // seconds, i64::MAX while nothing accrues
+    interest_paused: bool,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct LiquidStaked{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    receipt_amount: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct LiquidWithdrawRequested{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    receipt_amount: u64,
+    amount: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct PositionTransferred{
+    nft_mint: Pubkey,
+    previous_owner: Pubkey,
+    new_owner: Pubkey,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct StakedFor{
+    funder: Pubkey,
+    beneficiary: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    locked_until: i64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct BasketMintUpdated{
+    token_mint: Pubkey,
+    cap: u64,
+    enabled: bool,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct BasketStaked{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    normalized_amount: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct BasketInterestFunded{
+    token_mint: Pubkey,
+    amount: u64,
+    normalized_amount: u64,
+    interest_budget: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct BasketWithdrawn{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    normalized_amount: u64,
+    interest_amount: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct PayoutAddressUpdated{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    payout_address: Pubkey,
+    effective_time: i64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct RewardsClaimed{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    amount: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct LotsMerged{
+    staker: Pubkey,
+    token_mint: Pubkey,
+    merged_lots: u64,
+    amount: u64,
+    interest_amount: u64,
+    time_stamp: i64,
+}
+
+#[event]
+pub struct PoolSynced{
+    token_mint: Pubkey,
+    recorded_amount: u64,
+    vault_amount: u64,
+    liabilities: u64,
+    surplus: u64,
+    shortfall: u64,
+    time_stamp: i64,
+}
+
+//Token accounts moved by transfer_checked, anchor-spl 0.24 has no wrapper for it
+pub struct TransferChecked<'info> {
+    pub from: AccountInfo<'info>,
+    pub mint: AccountInfo<'info>,
+    pub to: AccountInfo<'info>,
+    pub authority: AccountInfo<'info>,
+}
+
+impl<'info> ToAccountMetas for TransferChecked<'info> {
+    fn to_account_metas(&self, is_signer: Option<bool>) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.from.key(), false),
+            AccountMeta::new_readonly(self.mint.key(), false),
+            AccountMeta::new(self.to.key(), false),
+            AccountMeta::new_readonly(self.authority.key(), is_signer.unwrap_or(true)),
+        ]
+    }
+}
+
+impl<'info> ToAccountInfos<'info> for TransferChecked<'info> {
+    fn to_account_infos(&self) -> Vec<AccountInfo<'info>> {
+        vec![
+            self.from.clone(),
+            self.mint.clone(),
+            self.to.clone(),
+            self.authority.clone(),
+        ]
+    }
+}
+
+//Transfer that the token program rejects unless `decimals` matches the mint
+fn transfer_checked<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+    amount: u64,
+    decimals: u8
+) -> Result<()> {
+    let transfer_instruction = anchor_spl::token::spl_token::instruction::transfer_checked(
+        &anchor_spl::token::ID,
+        &ctx.accounts.from.key(),
+        &ctx.accounts.mint.key(),
+        &ctx.accounts.to.key(),
+        &ctx.accounts.authority.key(),
+        &[],
+        amount,
+        decimals
+    )?;
+    anchor_lang::solana_program::program::invoke_signed(
+        &transfer_instruction,
+        &ctx.to_account_infos(),
+        ctx.signer_seeds
+    )?;
+
+    Ok(())
+}
+
+//Convert an admin amount with `amount_decimals` fractional digits to base units of a mint with `mint_decimals`
+fn to_base_units(amount: u64, amount_decimals: u8, mint_decimals: u8) -> Result<u64> {
+    require!(amount_decimals <= mint_decimals, ErrorCode::InvalidTokenAmount);
+    10u64.checked_pow((mint_decimals - amount_decimals) as u32)
+        .and_then(|unit| amount.checked_mul(unit))
+        .ok_or_else(|| error!(ErrorCode::InvalidTokenAmount))
+}
+
+//Rescale `amount` from `from_decimals` to `to_decimals`, rounding down
+fn normalize_amount(amount: u64, from_decimals: u8, to_decimals: u8) -> Result<u64> {
+    let scaled = if to_decimals >= from_decimals {
+        10u128.checked_pow((to_decimals - from_decimals) as u32)
+            .and_then(|unit| (amount as u128).checked_mul(unit))
+    } else {
+        10u128.checked_pow((from_decimals - to_decimals) as u32)
+            .map(|unit| amount as u128 / unit)
+    };
+    scaled.and_then(|scaled| u64::try_from(scaled).ok())
+        .ok_or_else(|| error!(ErrorCode::InvalidTokenAmount))
+}
+
+//Reallocate LockedPool to hold at least `lots` lots, the staker pays the extra rent
+fn realloc_locked_pool<'info>(
+    lock_pool_action: &AccountInfo<'info>,
+    staker: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    lots: usize
+) -> Result<()> {
+    if lock_pool_action.data_len() >= LockedPool::space(lots) {
+        return Ok(());
+    }
+    realloc_account(lock_pool_action, staker, system_program, LockedPool::space(lots - 1 + LOT_GROWTH))
+}
+
+//Grow `account` to `new_len` zeroed bytes, the payer tops up the rent
+fn realloc_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    new_len: usize
+) -> Result<()> {
+    if account.data_len() >= new_len {
+        return Ok(());
+    }
+
+    let required_lamports = Rent::get()?.minimum_balance(new_len);
+    let current_lamports = account.lamports();
+    if required_lamports > current_lamports {
+        let transfer_instruction = anchor_lang::system_program::Transfer{
+            from: payer.clone(),
+            to: account.clone(),
+        };
+        let cpi_ctx = CpiContext::new(
+            system_program.clone(),
+            transfer_instruction,
+        );
+        anchor_lang::system_program::transfer(cpi_ctx, required_lamports - current_lamports)?;
+    }
+
+    account.realloc(new_len, true)?;
+
+    Ok(())
+}
+
+//Shrink LockedPool down to `lots` lots, keeping the minimum capacity, and refund the freed rent
+fn shrink_locked_pool<'info>(
+    lock_pool_action: &AccountInfo<'info>,
+    staker: &AccountInfo<'info>,
+    lots: usize
+) -> Result<()> {
+    let new_len = LockedPool::space(lots.max(LOT_GROWTH));
+    if lock_pool_action.data_len() <= new_len {
+        return Ok(());
+    }
+
+    lock_pool_action.realloc(new_len, false)?;
+
+    let required_lamports = Rent::get()?.minimum_balance(new_len);
+    let refund = lock_pool_action.lamports().saturating_sub(required_lamports);
+    **lock_pool_action.try_borrow_mut_lamports()? -= refund;
+    **staker.try_borrow_mut_lamports()? += refund;
+
+    Ok(())
+}
+
+//Book a stake that already landed in the vault as a new lot
+fn book_stake(
+    staking_pool: &mut StakePool,
+    pool_action: &mut PoolAction,
+    locked_pool_action: &mut LockedPool,
+    action_amount: u64,
+    locked_until: i64,
+    current_interest: u8,
+    current_time: i64
+) -> Result<()> {
+    //Accrue pool interest up to now
+    staking_pool.accrue_interest(current_interest, current_time);
+
+    //Circuit breaker blocks new stakes while the pool is under-covered
+    require!(!staking_pool.circuit_breaker, ErrorCode::PoolUndercollateralized);
+    staking_pool.check_stake_limits(action_amount, pool_action.staked())?;
+
+    //Update Staking Pool
+    staking_pool.token_amount += action_amount;
+    staking_pool.total_staked += action_amount;
+
+    // Update Pool Action
+    let token_amount = pool_action.token_amount + action_amount;
+    staking_pool.set_weight(pool_action, token_amount, current_time);
+    pool_action.touch(current_time);
+
+    //Reuse the first empty lot before appending a new one
+    let total_length = locked_pool_action.locked_amount.len();
+    let interest_start = staking_pool.interest_clock(current_time);
+    locked_pool_action.pad_lots();
+    let mut pool_updated = false;
+    for n in 0..total_length {
+        if locked_pool_action.locked_amount[n] == 0 && locked_pool_action.locked_start_time[n] == 0 {
+            locked_pool_action.locked_amount[n] = action_amount;
+            locked_pool_action.locked_start_time[n] = current_time;
+            locked_pool_action.locked_until[n] = locked_until;
+            locked_pool_action.locked_interest_start[n] = interest_start;
+            pool_updated = true;
+            break;
+        }
+    }
+    if !pool_updated{
+        locked_pool_action.locked_amount.push(action_amount);
+        locked_pool_action.locked_start_time.push(current_time);
+        locked_pool_action.locked_until.push(locked_until);
+        locked_pool_action.locked_interest_start.push(interest_start);
+    }
+
+    Ok(())
+}
+
+//Move locked lots into the staker's withdraw request, returns the interest settled on them
+fn book_withdraw_request(
+    staking_pool: &mut StakePool,
+    pool_action: &mut PoolAction,
+    locked_pool_action: &mut LockedPool,
+    withdraw_pool_action: &mut WithdrawRequest,
+    action_amount: u64,
+    current_interest: u8,
+    current_time: i64
+) -> Result<u64> {
+    require!(action_amount > 0, ErrorCode::InvalidTokenAmount);
+
+    //Accrue pool interest up to now
+    staking_pool.accrue_interest(current_interest, current_time);
+
+    //Handle Withdraw Request
+    // Queue their withdraw to next sunday
+    // Withdraw request can only be made from Monday to Friday 
+    let day_of_week = (current_time/86400 + 4)%7;
+    require!(day_of_week != 1, ErrorCode::InvalidWithdrawDay);
+
+    //withdraw action amount update
+    let withdraw_action_amount = withdraw_pool_action.requested_amount + action_amount;
+    let locked_before: u64 = locked_pool_action.locked_amount.iter().sum();
+    let mut realized_interest: u64 = 0;
+    let mut skipped_locked = false;
+    let interest_clock = staking_pool.interest_clock(current_time);
+    locked_pool_action.pad_lots();
+
+    for n in 0..locked_pool_action.locked_amount.len() {
+        //Gifted lots stay put until their lock date
+        if locked_pool_action.lock_of(n) > current_time {
+            skipped_locked |= locked_pool_action.locked_amount[n] > 0;
+            continue;
+        }
+        //Immature lots only leave through early_withdraw
+        if !lot_matured(locked_pool_action.locked_start_time[n], current_time) {
+            continue;
+        }
+        let (amount, start_time, interest_amount) = settle_lot(
+            locked_pool_action.locked_amount[n],
+            locked_pool_action.locked_start_time[n],
+            staking_pool.interest_elapsed(locked_pool_action.locked_interest_start[n], current_time),
+            current_interest,
+            &mut withdraw_pool_action.requested_amount,
+            withdraw_action_amount
+        );
+        locked_pool_action.locked_amount[n] = amount;
+        locked_pool_action.locked_start_time[n] = start_time;
+        locked_pool_action.locked_interest_start[n] = interest_clock;
+        realized_interest += interest_amount;
+    }
+    //Check Unlocked Amount i.e. exceeded 15 days Locking
+    require!(withdraw_pool_action.requested_amount >= action_amount || !skipped_locked, ErrorCode::StakeLockedUntil);
+    require!(withdraw_pool_action.requested_amount >= action_amount, ErrorCode::LockingPeriod);
+
+    require!(staking_pool.token_amount >= action_amount, ErrorCode::NotEnoughToken);
+    let token_amount = pool_action.token_amount.saturating_sub(action_amount);
+    staking_pool.set_weight(pool_action, token_amount, current_time);
+    pool_action.touch(current_time);
+
+    //Update Withdraw Pool Time
+    withdraw_pool_action.requested_time = current_time;
+
+    //Move settled principal and interest into pending withdrawals
+    let locked_after: u64 = locked_pool_action.locked_amount.iter().sum();
+    staking_pool.book_withdraw_request(locked_before, locked_after, realized_interest, action_amount);
+
+    Ok(realized_interest)
+}
+
+//Write the next history entry, stakes are confirmed right away and withdraw requests on claim
+fn record_entry(
+    pool_action_entry: &mut PoolActionEntry,
+    pool_count: &mut Count,
+    staker: Pubkey,
+    action_amount: u64,
+    stake_action: bool,
+    current_time: i64
+) {
+    pool_action_entry.stake_action = stake_action;
+    pool_action_entry.staker = staker;
+    pool_action_entry.token_amount = action_amount;
+    pool_action_entry.time_stamp = current_time;
+    pool_action_entry.confirmed = stake_action;
+
+    //Entry index is owned by the program, never reused
+    pool_count.count += 1;
+}
+
+//Move one lot into a withdraw request of `target_amount`
+//Returns the lot's remaining amount, start time and the interest settled on it
+fn settle_lot(
+    amount: u64,
+    start_time: i64,
+    interest_elapsed: i64,
+    interest: u8,
+    requested_amount: &mut u64,
+    target_amount: u64
+) -> (u64, i64, u64) {
+    let interest_amount = calculate_interest(amount, interest, interest_elapsed);
+    *requested_amount += amount + interest_amount;
+
+    if *requested_amount > target_amount {
+        //The remainder stays matured, callers restart its interest clock
+        let remaining_amount = *requested_amount - target_amount;
+        *requested_amount = target_amount;
+        (remaining_amount, start_time, interest_amount)
+    }
+    else {
+        (0, 0, interest_amount)
+    }
+}
+
+//A lot can only be requested normally once its locking period is over
+fn lot_matured(start_time: i64, current_time: i64) -> bool {
+    start_time + LOCKING_PERIOD <= current_time
+}
+
+//Release up to `limit` of a lot's value early
+//Returns the principal and interest taken and the penalty charged on them
+fn release_lot_early(
+    amount: u64,
+    start_time: i64,
+    interest_elapsed: i64,
+    interest: u8,
+    current_time: i64,
+    limit: u64,
+    penalty_config: &PenaltyConfig
+) -> (u64, u64, u64) {
+    let interest_amount = calculate_interest(amount, interest, interest_elapsed);
+    let lot_value = amount + interest_amount;
+    let taken = lot_value.min(limit);
+
+    //Split proportionally so the rest of the lot keeps its start time
+    let principal_taken = if taken == lot_value {
+        amount
+    } else {
+        (amount as u128 * taken as u128 / lot_value as u128) as u64
+    };
+    let interest_taken = taken - principal_taken;
+
+    //Penalty shrinks linearly to zero at maturity
+    let remaining_lock = (start_time + LOCKING_PERIOD - current_time).clamp(0, LOCKING_PERIOD) as u128;
+    let mut penalty = principal_taken as u128 * penalty_config.penalty_rate as u128 / 10000;
+    if penalty_config.forfeit_interest {
+        penalty += interest_taken as u128;
+    }
+    let penalty = (penalty * remaining_lock / LOCKING_PERIOD as u128) as u64;
+
+    (principal_taken, interest_taken, penalty)
+}
+
+//Simple interest for `amount` at an annual `interest` percent over `elapsed` seconds
+fn calculate_interest(amount: u64, interest: u8, elapsed: i64) -> u64 {
+    if elapsed <= 0 {
+        return 0;
+    }
+    let interest_amount = amount as u128 * interest as u128 * elapsed as u128 / (100 * 31536000);
+    u64::try_from(interest_amount).unwrap_or(u64::MAX)
 }
 
 #[error_code]
@@ -767,5 +5961,89 @@ pub enum ErrorCode {
     #[msg("Our team is working on your withdrawal, please wait till Sunday at 17.00 UTC in order to claim your rewards")]
     ExceedPoolAmount,
 
+    #[msg("Pool Coverage Below Threshold")]
+    PoolUndercollateralized,
+
+    #[msg("Entry Can Be Closed After 30 Days")]
+    EntryTooRecent,
+
+    #[msg("Position Still Has Principal Or Pending Withdraw")]
+    PositionNotEmpty,
+
+    #[msg("No Free Lot Left In Position")]
+    PositionFull,
+
+    #[msg("Signer Does Not Hold The Position NFT")]
+    NotPositionHolder,
+
+    #[msg("Stake Is Locked Until A Later Date")]
+    StakeLockedUntil,
+
+    #[msg("Lock Date Is Too Far In The Future")]
+    InvalidLockDate,
+
+    #[msg("Invalid Payout Address")]
+    InvalidPayoutAddress,
+
+    #[msg("Payout Change Delay Cannot Be Negative")]
+    InvalidPayoutDelay,
+
+    #[msg("Pool Is Not A Native SOL Pool")]
+    NotNativeMint,
+
+    #[msg("Vault Received Less Than The Transfer Tolerance Allows")]
+    TransferShortfall,
+
+    #[msg("Invalid Transfer Tolerance")]
+    InvalidTransferTolerance,
+
+    #[msg("Amount Does Not Fit The Mint's Decimals")]
+    InvalidTokenAmount,
+
+    #[msg("Mint Is Not Enabled In The Basket")]
+    BasketMintDisabled,
+
+    #[msg("Basket Cap For This Mint Exceeded")]
+    BasketMintCapExceeded,
+
+    #[msg("Not Enough Liquidity In This Mint")]
+    InsufficientBasketLiquidity,
+
+    #[msg("Stake Is Below The Pool Minimum")]
+    StakeBelowMinimum,
+
+    #[msg("Stake Exceeds The Per-User Maximum")]
+    StakeAboveUserMaximum,
+
+    #[msg("Stake Exceeds The Pool Capacity")]
+    PoolCapacityExceeded,
+
+    #[msg("Invalid Stake Limits")]
+    InvalidStakeLimits,
+
+    #[msg("Invalid Penalty Config")]
+    InvalidPenaltyConfig,
+
+    #[msg("Nothing To Claim")]
+    NothingToClaim,
+
+    #[msg("Invalid Reward Schedule")]
+    InvalidRewardSchedule,
+
+    #[msg("Reward Schedule Active")]
+    RewardScheduleActive,
+
+    #[msg("Insufficient Reward Funding")]
+    InsufficientRewardFunding,
+
+    #[msg("Mint Is Not Owned By The SPL Token Program")]
+    UnsupportedTokenProgram,
+
+    #[msg("Position Is Already Bound To The Signer")]
+    PositionAlreadyBound,
+
+    #[msg("Locked Gifts Must Be Signed By The Beneficiary")]
+    GiftLockNotAccepted,
+
 
 }
@@ -4,6 +4,7 @@ import { StakingContract } from '../target/types/staking_contract';
 
 import * as spl from '@solana/spl-token';
 import { assert, expect } from 'chai';
+import * as fs from 'fs';
 
 interface PDAParameters {
   stake_pool: anchor.web3.PublicKey;
@@ -41,6 +42,19 @@ describe('Test Stake Unstake etc', () => {
 
   let stakingVaultAssociatedAddress: anchor.web3.PublicKey;
 
+  let erin: anchor.web3.Keypair;
+  let erinTokenAccount: anchor.web3.PublicKey;
+
+  // Loaded by Anchor.toml from tests/fixtures in the layout of the first release
+  const legacyMint = new anchor.web3.PublicKey(
+    'fBnYubHPDmMfBMYC9BCMtkDdbo6paqA3s5kMTwM8z3J'
+  );
+  const legacyStaker = anchor.web3.Keypair.fromSecretKey(
+    Uint8Array.from(
+      JSON.parse(fs.readFileSync('tests/fixtures/legacy-staker.json', 'utf8'))
+    )
+  );
+
   const fundWallet = async (user: anchor.web3.PublicKey, amount: number) => {
     let txFund = new anchor.web3.Transaction();
     txFund.add(
@@ -119,7 +133,7 @@ describe('Test Stake Unstake etc', () => {
             Buffer.from('pool_entry'),
             signer.toBuffer(),
             token_mint.toBuffer(),
-            new anchor.BN(last_count).toArrayLike(Buffer),
+            new anchor.BN(last_count).toArrayLike(Buffer, 'le', 8),
           ],
           program.programId
         );
@@ -180,7 +194,7 @@ describe('Test Stake Unstake etc', () => {
           Buffer.from('pool_entry'),
           signer.toBuffer(),
           token_mint.toBuffer(),
-          new anchor.BN(next_count).toArrayLike(Buffer),
+          new anchor.BN(next_count).toArrayLike(Buffer, 'le', 8),
         ],
         program.programId
       );
@@ -203,7 +217,9 @@ describe('Test Stake Unstake etc', () => {
   };
 
   //Create a SPL Token
-  const createMint = async (): Promise<anchor.web3.PublicKey> => {
+  const createMint = async (
+    decimals: number = 6
+  ): Promise<anchor.web3.PublicKey> => {
     const tokenMint = new anchor.web3.Keypair();
     const lamportsForMint =
       await provider.connection.getMinimumBalanceForRentExemption(
@@ -225,7 +241,7 @@ describe('Test Stake Unstake etc', () => {
     tx.add(
       spl.createInitializeMintInstruction(
         tokenMint.publicKey,
-        6,
+        decimals,
         provider.wallet.publicKey,
         provider.wallet.publicKey,
         spl.TOKEN_PROGRAM_ID
@@ -325,6 +341,314 @@ describe('Test Stake Unstake etc', () => {
     return accountInfo.amount.toString();
   };
 
+  //Stake or request a withdraw for any user
+  const performActionFor = async (
+    user: anchor.web3.Keypair,
+    userTokenAccount: anchor.web3.PublicKey,
+    amount: string,
+    stake_action: boolean
+  ): Promise<string> => {
+    let userPda = await getPdaParams(mintAddress, user.publicKey);
+    let pool_entry_pda = await getLatestEntryPDA(user.publicKey, mintAddress);
+    let pool_count_pda = await getEntryCountPDA(user.publicKey, mintAddress);
+    let tokenInterestPda = await getTokenInterestPDA(mintAddress);
+
+    return await program.rpc.performAction(
+      new anchor.BN(amount),
+      mintAddress,
+      stake_action,
+      0,
+      {
+        accounts: {
+          staker: user.publicKey,
+          currentStakingPool: userPda.stake_pool,
+          poolAction: userPda.pool_action,
+          lockPoolAction: userPda.lock_pool,
+          withdrawPoolAction: userPda.withdraw_pool,
+          poolEntry: pool_entry_pda,
+          poolCount: pool_count_pda,
+          tokenInterest: tokenInterestPda,
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          stakerAssociatedAddress: userTokenAccount,
+          tokenMint: mintAddress,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [user],
+      }
+    );
+  };
+
+  const stakeFor = async (
+    user: anchor.web3.Keypair,
+    userTokenAccount: anchor.web3.PublicKey,
+    amount: string,
+    token_mint: anchor.web3.PublicKey = mintAddress
+  ): Promise<string> => {
+    let userPda = await getPdaParams(token_mint, user.publicKey);
+
+    return await program.rpc.stake(new anchor.BN(amount), {
+      accounts: {
+        staker: user.publicKey,
+        currentStakingPool: userPda.stake_pool,
+        poolAction: userPda.pool_action,
+        lockPoolAction: userPda.lock_pool,
+        poolCount: await getEntryCountPDA(user.publicKey, token_mint),
+        poolEntry: await getLatestEntryPDA(user.publicKey, token_mint),
+        tokenInterest: await getTokenInterestPDA(token_mint),
+        stakingVaultAssociatedAddress: await spl.getAssociatedTokenAddress(
+          token_mint,
+          userPda.stake_pool,
+          true
+        ),
+        stakerAssociatedAddress: userTokenAccount,
+        tokenMint: token_mint,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [user],
+    });
+  };
+
+  const stakeForBeneficiary = async (
+    funder: anchor.web3.Keypair,
+    funderTokenAccount: anchor.web3.PublicKey,
+    beneficiary: anchor.web3.PublicKey,
+    amount: string,
+    lockUntil: number,
+    beneficiarySigner?: anchor.web3.Keypair
+  ): Promise<string> => {
+    let beneficiaryPda = await getPdaParams(mintAddress, beneficiary);
+
+    return await program.rpc.stakeFor(
+      new anchor.BN(amount),
+      new anchor.BN(lockUntil),
+      {
+        accounts: {
+          funder: funder.publicKey,
+          beneficiary: beneficiary,
+          currentStakingPool: beneficiaryPda.stake_pool,
+          poolAction: beneficiaryPda.pool_action,
+          lockPoolAction: beneficiaryPda.lock_pool,
+          poolCount: await getEntryCountPDA(beneficiary, mintAddress),
+          poolEntry: await getLatestEntryPDA(beneficiary, mintAddress),
+          tokenInterest: await getTokenInterestPDA(mintAddress),
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          funderAssociatedAddress: funderTokenAccount,
+          tokenMint: mintAddress,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: beneficiarySigner ? [funder, beneficiarySigner] : [funder],
+      }
+    );
+  };
+
+  const requestWithdrawFor = async (
+    user: anchor.web3.Keypair,
+    amount: string
+  ): Promise<string> => {
+    let userPda = await getPdaParams(mintAddress, user.publicKey);
+
+    return await program.rpc.requestWithdraw(new anchor.BN(amount), {
+      accounts: {
+        staker: user.publicKey,
+        currentStakingPool: userPda.stake_pool,
+        poolAction: userPda.pool_action,
+        lockPoolAction: userPda.lock_pool,
+        withdrawPoolAction: userPda.withdraw_pool,
+        poolCount: await getEntryCountPDA(user.publicKey, mintAddress),
+        poolEntry: await getLatestEntryPDA(user.publicKey, mintAddress),
+        tokenInterest: await getTokenInterestPDA(mintAddress),
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [user],
+    });
+  };
+
+  const getPenaltyConfigPDA = async (
+    token_mint: anchor.web3.PublicKey
+  ): Promise<anchor.web3.PublicKey> => {
+    const penaltyConfig = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('penalty_config'), token_mint.toBuffer()],
+      program.programId
+    );
+
+    return penaltyConfig[0];
+  };
+
+  //Drop the early withdraw penalty so tests can leave fresh lots at par
+  const clearPenaltyConfig = async (
+    token_mint: anchor.web3.PublicKey = mintAddress
+  ): Promise<string> => {
+    let [stake_pool] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('stake_pool'), token_mint.toBuffer()],
+      program.programId
+    );
+
+    return await program.rpc.updatePenaltyConfig(
+      new anchor.BN(0),
+      false,
+      2,
+      bobAdminTokenAccount,
+      bobAdminTokenAccount,
+      new anchor.BN(0),
+      0,
+      {
+        accounts: {
+          admin: bobAdmin.publicKey,
+          penaltyConfig: await getPenaltyConfigPDA(token_mint),
+          adminConfig: adminConfig,
+          currentStakingPool: stake_pool,
+          tokenMint: token_mint,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [bobAdmin],
+      }
+    );
+  };
+
+  //Admin withdraw of vault tokens nobody is owed
+  const rescueToken = async (
+    owner: anchor.web3.Keypair,
+    ownerTokenAccount: anchor.web3.PublicKey,
+    amount: string,
+    token_mint: anchor.web3.PublicKey = mintAddress
+  ): Promise<string> => {
+    let [stake_pool] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('stake_pool'), token_mint.toBuffer()],
+      program.programId
+    );
+
+    return await program.rpc.rescuseToken(new anchor.BN(amount), {
+      accounts: {
+        owner: owner.publicKey,
+        currentStakingPool: stake_pool,
+        adminConfig: adminConfig,
+        stakingVaultAssociatedAddress: await spl.getAssociatedTokenAddress(
+          token_mint,
+          stake_pool,
+          true
+        ),
+        adminAssociatedAddress: ownerTokenAccount,
+        tokenInterest: await getTokenInterestPDA(token_mint),
+        tokenMint: token_mint,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [owner],
+    });
+  };
+
+  //Lots only leave before their locking period through early_withdraw
+  const earlyWithdrawFor = async (
+    user: anchor.web3.Keypair,
+    amount: string,
+    token_mint: anchor.web3.PublicKey = mintAddress,
+    penaltyDestinationAccount: anchor.web3.PublicKey = bobAdminTokenAccount
+  ): Promise<string> => {
+    let userPda = await getPdaParams(token_mint, user.publicKey);
+
+    return await program.rpc.earlyWithdraw(new anchor.BN(amount), {
+      accounts: {
+        staker: user.publicKey,
+        currentStakingPool: userPda.stake_pool,
+        poolAction: userPda.pool_action,
+        lockPoolAction: userPda.lock_pool,
+        withdrawPoolAction: userPda.withdraw_pool,
+        poolCount: await getEntryCountPDA(user.publicKey, token_mint),
+        poolEntry: await getLatestEntryPDA(user.publicKey, token_mint),
+        tokenInterest: await getTokenInterestPDA(token_mint),
+        penaltyConfig: await getPenaltyConfigPDA(token_mint),
+        stakingVaultAssociatedAddress: await spl.getAssociatedTokenAddress(
+          token_mint,
+          userPda.stake_pool,
+          true
+        ),
+        penaltyDestinationAccount: penaltyDestinationAccount,
+        tokenMint: token_mint,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [user],
+    });
+  };
+
+  const getLotPositionPDA = async (
+    signer: anchor.web3.PublicKey,
+    token_mint: anchor.web3.PublicKey
+  ): Promise<anchor.web3.PublicKey> => {
+    let [lot_position, lot_position_bump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from('lot_position'), signer.toBuffer(), token_mint.toBuffer()],
+        program.programId
+      );
+
+    return lot_position;
+  };
+
+  const getPayoutConfigPDA = async (
+    signer: anchor.web3.PublicKey,
+    token_mint: anchor.web3.PublicKey
+  ): Promise<anchor.web3.PublicKey> => {
+    let [payout_config, payout_config_bump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from('payout_config'), signer.toBuffer(), token_mint.toBuffer()],
+        program.programId
+      );
+
+    return payout_config;
+  };
+
+  //Parse the program events emitted by a transaction
+  const getEvents = async (txSig: string): Promise<any[]> => {
+    await provider.connection.confirmTransaction(txSig, 'confirmed');
+    const tx = await provider.connection.getTransaction(txSig, {
+      commitment: 'confirmed',
+    });
+    const eventParser = new anchor.EventParser(
+      program.programId,
+      program.coder
+    );
+    let events = [];
+    eventParser.parseLogs(tx.meta.logMessages, (event) => events.push(event));
+    return events;
+  };
+
+  //Pools are created by the admin before anyone stakes
+  const createPool = async (
+    token_mint: anchor.web3.PublicKey
+  ): Promise<string> => {
+    let [stake_pool] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('stake_pool'), token_mint.toBuffer()],
+      program.programId
+    );
+
+    return await program.rpc.createPool({
+      accounts: {
+        admin: bobAdmin.publicKey,
+        currentStakingPool: stake_pool,
+        adminConfig: adminConfig,
+        tokenMint: token_mint,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [bobAdmin],
+    });
+  };
+
   before(async () => {
     //c8 mint token
     mintAddress = await createMint();
@@ -373,7 +697,7 @@ describe('Test Stake Unstake etc', () => {
     let info = await program.account.config.fetch(adminConfig);
     assert.equal(info.admin.toString(), tempAdmin.publicKey.toString());
 
-    await program.rpc.updateAdminWallet(bobAdmin.publicKey, {
+    let txn = await program.rpc.updateAdminWallet(bobAdmin.publicKey, {
       accounts: {
         owner: tempAdmin.publicKey,
         adminConfig: adminConfig,
@@ -385,6 +709,37 @@ describe('Test Stake Unstake etc', () => {
 
     info = await program.account.config.fetch(adminConfig);
     assert.equal(info.admin.toString(), bobAdmin.publicKey.toString());
+
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'AdminChanged');
+    assert.equal(
+      events[0].data.oldAdmin.toString(),
+      tempAdmin.publicKey.toString()
+    );
+    assert.equal(
+      events[0].data.newAdmin.toString(),
+      bobAdmin.publicKey.toString()
+    );
+  });
+
+  it('Create Pool', async () => {
+    let txn = await createPool(mintAddress);
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'PoolCreated');
+
+    let stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.equal(stakePool.tokenMint.toBase58(), mintAddress.toBase58());
+    assert.equal(stakePool.decimals, 6);
+
+    // Only mints owned by the SPL Token program get a pool
+    let notAMint = new anchor.web3.Keypair();
+    await fundWallet(notAMint.publicKey, 1);
+    try {
+      await createPool(notAMint.publicKey);
+      assert.fail('account outside the token program should be rejected');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'UnsupportedTokenProgram');
+    }
   });
 
   it('Update Interest Rate', async () => {
@@ -396,6 +751,7 @@ describe('Test Stake Unstake etc', () => {
     let txn = await program.rpc.updateInterestRate(interestRate, {
       accounts: {
         admin: bobAdmin.publicKey,
+        currentStakingPool: pda.stake_pool,
         tokenInterest: tokenInterestPda,
         adminConfig: adminConfig,
         tokenMint: mintAddress,
@@ -411,6 +767,10 @@ describe('Test Stake Unstake etc', () => {
       tokenInterestPda
     );
     assert.equal(tokenInterest.interest.toString(), '2');
+
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'InterestRateUpdated');
+    assert.equal(events[0].data.newInterest.toString(), '2');
   });
 
   it('Staking1', async () => {
@@ -419,15 +779,12 @@ describe('Test Stake Unstake etc', () => {
 
     let pool_entry_pda = await getLatestEntryPDA(alice.publicKey, mintAddress);
     let pool_count_pda = await getEntryCountPDA(alice.publicKey, mintAddress);
-    let latest_count = await getLastEntryCount(alice.publicKey, mintAddress);
     let tokenInterestPda = await getTokenInterestPDA(mintAddress);
-
-    let next_count = parseInt(latest_count) + 1;
     let txn = await program.rpc.performAction(
       new anchor.BN(staking_amount),
       mintAddress,
       stake_action,
-      new anchor.BN(next_count),
+      0,
       {
         accounts: {
           staker: alice.publicKey,
@@ -454,6 +811,12 @@ describe('Test Stake Unstake etc', () => {
 
     let stakingVaultBalance = await readAccount(stakingVaultAssociatedAddress);
     assert.equal(stakingVaultBalance, staking_amount);
+
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'Staked');
+    assert.equal(events[0].data.staker.toString(), alice.publicKey.toString());
+    assert.equal(events[0].data.tokenMint.toString(), mintAddress.toString());
+    assert.equal(events[0].data.amount.toString(), staking_amount);
   });
 
   it('Staking2', async () => {
@@ -462,15 +825,12 @@ describe('Test Stake Unstake etc', () => {
 
     let pool_entry_pda = await getLatestEntryPDA(alice.publicKey, mintAddress);
     let pool_count_pda = await getEntryCountPDA(alice.publicKey, mintAddress);
-    let latest_count = await getLastEntryCount(alice.publicKey, mintAddress);
     let tokenInterestPda = await getTokenInterestPDA(mintAddress);
-
-    let next_count = parseInt(latest_count) + 1;
     let txn = await program.rpc.performAction(
       new anchor.BN(staking_amount),
       mintAddress,
       stake_action,
-      new anchor.BN(next_count),
+      0,
       {
         accounts: {
           staker: alice.publicKey,
@@ -500,47 +860,36 @@ describe('Test Stake Unstake etc', () => {
   });
 
   it('Withdraw Request', async () => {
-    let un_staking_amount = '500000';
-    let stake_action = false;
+    let un_staking_amount = '5000000';
 
-    let pool_entry_pda = await getLatestEntryPDA(alice.publicKey, mintAddress);
-    let pool_count_pda = await getEntryCountPDA(alice.publicKey, mintAddress);
-    let latest_count = await getLastEntryCount(alice.publicKey, mintAddress);
-    let tokenInterestPda = await getTokenInterestPDA(mintAddress);
+    try {
+      await performActionFor(alice, aliceTokenAccount, '0', false);
+      assert.fail('an empty withdraw request should be rejected');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidTokenAmount');
+    }
 
-    let next_count = parseInt(latest_count) + 1;
+    // Both lots are still inside their locking period
+    try {
+      await performActionFor(alice, aliceTokenAccount, un_staking_amount, false);
+      assert.fail('immature lots should not be requested');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'LockingPeriod');
+    }
 
-    let txn = await program.rpc.performAction(
-      new anchor.BN(un_staking_amount),
-      mintAddress,
-      stake_action,
-      next_count,
-      {
-        accounts: {
-          staker: alice.publicKey,
-          currentStakingPool: pda.stake_pool,
-          poolAction: pda.pool_action,
-          lockPoolAction: pda.lock_pool,
-          withdrawPoolAction: pda.withdraw_pool,
-          poolEntry: pool_entry_pda,
-          poolCount: pool_count_pda,
-          tokenInterest: tokenInterestPda,
-          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
-          stakerAssociatedAddress: aliceTokenAccount,
-          tokenMint: mintAddress,
-          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
-          systemProgram: anchor.web3.SystemProgram.programId,
-          tokenProgram: spl.TOKEN_PROGRAM_ID,
-          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
-        },
-        signers: [alice],
-      }
-    );
+    // Without a penalty the early withdraw queues the full amount
+    await clearPenaltyConfig();
+    let txn = await earlyWithdrawFor(alice, un_staking_amount);
     let stakingVaultBalance = await readAccount(stakingVaultAssociatedAddress);
     assert.equal(stakingVaultBalance, '20000000');
 
     let aliceBalance = await readAccount(aliceTokenAccount);
     assert.equal(aliceBalance, '0');
+
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'EarlyWithdrawn');
+    assert.equal(events[0].data.amount.toString(), un_staking_amount);
+    assert.equal(events[0].data.penaltyAmount.toString(), '0');
   });
 
   it('Withdraw Token On Sunday', async () => {
@@ -549,14 +898,11 @@ describe('Test Stake Unstake etc', () => {
 
     let pool_entry_pda = await getLatestEntryPDA(alice.publicKey, mintAddress);
     let pool_count_pda = await getEntryCountPDA(alice.publicKey, mintAddress);
-    let latest_count = await getLastEntryCount(alice.publicKey, mintAddress);
     let tokenInterestPda = await getTokenInterestPDA(mintAddress);
 
-    let next_count = parseInt(latest_count) + 1;
-
     let txn = await program.rpc.claimWithdraw(
       new anchor.BN(un_staking_amount),
-      next_count,
+      0,
       {
         accounts: {
           staker: alice.publicKey,
@@ -565,6 +911,8 @@ describe('Test Stake Unstake etc', () => {
           withdrawPoolAction: pda.withdraw_pool,
           poolEntry: pool_entry_pda,
           poolCount: pool_count_pda,
+          payoutConfig: await getPayoutConfigPDA(alice.publicKey, mintAddress),
+          payoutOwner: alice.publicKey,
           stakerAssociatedAddress: aliceTokenAccount,
           stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
           associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -580,17 +928,66 @@ describe('Test Stake Unstake etc', () => {
 
     let aliceBalance = await readAccount(aliceTokenAccount);
     assert.equal(aliceBalance, un_staking_amount);
+
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'WithdrawClaimed');
+    assert.equal(events[0].data.amount.toString(), un_staking_amount);
   });
 
   it('Withdraw Token By Admin', async () => {
     let withdraw_amount = '199999';
-    let txn = await program.rpc.rescuseToken(new anchor.BN(withdraw_amount), {
+
+    try {
+      await rescueToken(alice, aliceTokenAccount, withdraw_amount);
+      assert.fail('only the admin rescues tokens');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidAdmin');
+    }
+
+    // Every token in the vault is owed to alice
+    try {
+      await rescueToken(bobAdmin, bobAdminTokenAccount, withdraw_amount);
+      assert.fail('the vault would fall below its liabilities');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'PoolUndercollateralized');
+    }
+
+    // Tokens sent to the vault by mistake can be rescued
+    let txMint = new anchor.web3.Transaction();
+    txMint.add(
+      spl.createMintToInstruction(
+        mintAddress,
+        stakingVaultAssociatedAddress,
+        provider.wallet.publicKey,
+        Number(withdraw_amount),
+        [],
+        spl.TOKEN_PROGRAM_ID
+      )
+    );
+    await provider.sendAndConfirm(txMint);
+    let txn = await rescueToken(bobAdmin, bobAdminTokenAccount, withdraw_amount);
+
+    let adminBalance = await readAccount(bobAdminTokenAccount);
+    assert.equal(adminBalance, withdraw_amount);
+
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'TokensRescued');
+    assert.equal(events[0].data.amount.toString(), withdraw_amount);
+  });
+
+  it('Deposit Token By Admin', async () => {
+    let deposit_amount = '199999';
+    let initialAdminBalance = await readAccount(bobAdminTokenAccount);
+    let tokenInterestPda = await getTokenInterestPDA(mintAddress);
+
+    let txn = await program.rpc.depositToken(new anchor.BN(deposit_amount), {
       accounts: {
         owner: bobAdmin.publicKey,
         currentStakingPool: pda.stake_pool,
         adminConfig: adminConfig,
         stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
         adminAssociatedAddress: bobAdminTokenAccount,
+        tokenInterest: tokenInterestPda,
         tokenMint: mintAddress,
         associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
@@ -601,31 +998,2538 @@ describe('Test Stake Unstake etc', () => {
     });
 
     let adminBalance = await readAccount(bobAdminTokenAccount);
-    assert.equal(adminBalance, withdraw_amount);
+    assert.equal(adminBalance, initialAdminBalance - deposit_amount);
+
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'TokensDeposited');
+    assert.equal(events[0].data.amount.toString(), deposit_amount);
   });
 
-  it('Deposit Token By Admin', async () => {
-    let deposit_amount = '199999';
-    let initialAdminBalance = await readAccount(bobAdminTokenAccount);
+  it('Check Solvency', async () => {
+    let tokenInterestPda = await getTokenInterestPDA(mintAddress);
 
-    let txn = await program.rpc.depositToken(new anchor.BN(deposit_amount), {
+    // Require full coverage of liabilities
+    await program.rpc.updateCoverageThreshold(new anchor.BN(10000), {
       accounts: {
-        owner: bobAdmin.publicKey,
+        admin: bobAdmin.publicKey,
         currentStakingPool: pda.stake_pool,
         adminConfig: adminConfig,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [bobAdmin],
+    });
+
+    // Anyone can run the check
+    await program.rpc.checkSolvency({
+      accounts: {
+        currentStakingPool: pda.stake_pool,
+        tokenInterest: tokenInterestPda,
         stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
-        adminAssociatedAddress: bobAdminTokenAccount,
         tokenMint: mintAddress,
-        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
+      },
+    });
+
+    let stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.equal(stakePool.minCoverageRatio.toString(), '10000');
+    assert.equal(
+      stakePool.circuitBreaker,
+      stakePool.coverageRatio.lt(new anchor.BN(10000))
+    );
+
+    // Disable the threshold and lift the breaker with an empty deposit
+    await program.rpc.updateCoverageThreshold(new anchor.BN(0), {
+      accounts: {
+        admin: bobAdmin.publicKey,
+        currentStakingPool: pda.stake_pool,
+        adminConfig: adminConfig,
+        tokenMint: mintAddress,
         systemProgram: anchor.web3.SystemProgram.programId,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
       },
       signers: [bobAdmin],
     });
 
-    let adminBalance = await readAccount(bobAdminTokenAccount);
-    assert.equal(adminBalance, initialAdminBalance - deposit_amount);
+    await program.rpc.depositToken(new anchor.BN(0), {
+      accounts: {
+        owner: bobAdmin.publicKey,
+        currentStakingPool: pda.stake_pool,
+        adminConfig: adminConfig,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        adminAssociatedAddress: bobAdminTokenAccount,
+        tokenInterest: tokenInterestPda,
+        tokenMint: mintAddress,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [bobAdmin],
+    });
+
+    stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.isFalse(stakePool.circuitBreaker);
+  });
+
+  it('Sync Pool With Vault', async () => {
+    let tokenInterestPda = await getTokenInterestPDA(mintAddress);
+
+    await program.rpc.syncPool({
+      accounts: {
+        currentStakingPool: pda.stake_pool,
+        tokenInterest: tokenInterestPda,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        tokenMint: mintAddress,
+      },
+    });
+
+    let stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    let stakingVaultBalance = await readAccount(stakingVaultAssociatedAddress);
+    assert.equal(stakePool.tokenAmount.toString(), stakingVaultBalance);
+    // Only one side of the reconciliation can be booked
+    assert.isTrue(stakePool.surplus.isZero() || stakePool.shortfall.isZero());
+  });
+
+  it('Migrate Pool And Pool Action Keep Current Accounts', async () => {
+    let poolBefore = await provider.connection.getAccountInfo(pda.stake_pool);
+
+    try {
+      await program.rpc.migratePool({
+        accounts: {
+          admin: alice.publicKey,
+          currentStakingPool: pda.stake_pool,
+          adminConfig: adminConfig,
+          tokenMint: mintAddress,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        signers: [alice],
+      });
+      assert.fail('only the admin migrates pools');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidAdmin');
+    }
+
+    // Accounts already in the current layout are left as they are
+    await program.rpc.migratePool({
+      accounts: {
+        admin: bobAdmin.publicKey,
+        currentStakingPool: pda.stake_pool,
+        adminConfig: adminConfig,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      signers: [bobAdmin],
+    });
+    await program.rpc.migratePoolAction({
+      accounts: {
+        staker: alice.publicKey,
+        currentStakingPool: pda.stake_pool,
+        poolAction: pda.pool_action,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      signers: [alice],
+    });
+
+    let poolAfter = await provider.connection.getAccountInfo(pda.stake_pool);
+    assert.equal(poolAfter.data.length, poolBefore.data.length);
+    assert.isTrue(poolAfter.data.equals(poolBefore.data));
+    // Still deserializes after the no-op migration
+    await program.account.poolAction.fetch(pda.pool_action);
+  });
+
+  it('Migrate Pool Count Keeps A Widened Counter', async () => {
+    let poolCountPda = await getEntryCountPDA(alice.publicKey, mintAddress);
+    let countBefore = await program.account.count.fetch(poolCountPda);
+
+    await program.rpc.migratePoolCount({
+      accounts: {
+        staker: alice.publicKey,
+        poolCount: poolCountPda,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      signers: [alice],
+    });
+
+    let countAfter = await program.account.count.fetch(poolCountPda);
+    assert.equal(countAfter.count.toString(), countBefore.count.toString());
+  });
+
+  it('Migrate Legacy Pool Action Without Past Rewards', async () => {
+    let legacyPda = await getPdaParams(legacyMint, legacyStaker.publicKey);
+    let legacyVault = await spl.getAssociatedTokenAddress(
+      legacyMint,
+      legacyPda.stake_pool,
+      true
+    );
+    await fundWallet(legacyStaker.publicKey, 5);
+
+    const migratePoolAction = async () =>
+      program.rpc.migratePoolAction({
+        accounts: {
+          staker: legacyStaker.publicKey,
+          currentStakingPool: legacyPda.stake_pool,
+          poolAction: legacyPda.pool_action,
+          tokenMint: legacyMint,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        signers: [legacyStaker],
+      });
+
+    // Stakers follow once their pool is in the current layout
+    try {
+      await migratePoolAction();
+      assert.fail('the pool is still in the old layout');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'AccountDidNotDeserialize');
+    }
+    await program.rpc.migratePool({
+      accounts: {
+        admin: bobAdmin.publicKey,
+        currentStakingPool: legacyPda.stake_pool,
+        adminConfig: adminConfig,
+        tokenMint: legacyMint,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      signers: [bobAdmin],
+    });
+
+    // The vault holds the legacy stake, a new staker and the admin get tokens of the legacy mint
+    let [victor] = await createUserAndAssociatedWallet(undefined, 0);
+    let victorTokenAccount = await spl.getAssociatedTokenAddress(
+      legacyMint,
+      victor.publicKey
+    );
+    let legacyStakerTokenAccount = await spl.getAssociatedTokenAddress(
+      legacyMint,
+      legacyStaker.publicKey
+    );
+    let adminLegacyAccount = await spl.getAssociatedTokenAddress(
+      legacyMint,
+      bobAdmin.publicKey
+    );
+    let txFund = new anchor.web3.Transaction();
+    for (let [account, owner, amount] of [
+      [legacyVault, legacyPda.stake_pool, 5000],
+      [victorTokenAccount, victor.publicKey, 5000],
+      [legacyStakerTokenAccount, legacyStaker.publicKey, 0],
+      [adminLegacyAccount, bobAdmin.publicKey, 2000],
+    ] as [anchor.web3.PublicKey, anchor.web3.PublicKey, number][]) {
+      txFund.add(
+        spl.createAssociatedTokenAccountInstruction(
+          legacyStaker.publicKey,
+          account,
+          owner,
+          legacyMint
+        )
+      );
+      if (amount > 0) {
+        txFund.add(
+          spl.createMintToInstruction(
+            legacyMint,
+            account,
+            legacyStaker.publicKey,
+            amount
+          )
+        );
+      }
+    }
+    await provider.sendAndConfirm(txFund, [legacyStaker]);
+
+    // The legacy balance is booked as owed to its stakers
+    let stakePool = await program.account.stakePool.fetch(legacyPda.stake_pool);
+    assert.equal(stakePool.tokenAmount.toString(), '5000');
+    assert.equal(stakePool.totalStaked.toString(), '5000');
+    try {
+      await rescueToken(bobAdmin, adminLegacyAccount, '1', legacyMint);
+      assert.fail('the legacy stake is not rescuable');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'PoolUndercollateralized');
+    }
+
+    await stakeFor(victor, victorTokenAccount, '5000', legacyMint);
+
+    const depositRewards = async (amount: string) =>
+      program.rpc.depositRewards(new anchor.BN(amount), new anchor.BN(0), {
+        accounts: {
+          owner: bobAdmin.publicKey,
+          currentStakingPool: legacyPda.stake_pool,
+          adminConfig: adminConfig,
+          stakingVaultAssociatedAddress: legacyVault,
+          adminAssociatedAddress: adminLegacyAccount,
+          tokenMint: legacyMint,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        },
+        signers: [bobAdmin],
+      });
+    const claimRewards = async () =>
+      program.rpc.claimRewards({
+        accounts: {
+          staker: legacyStaker.publicKey,
+          currentStakingPool: legacyPda.stake_pool,
+          poolAction: legacyPda.pool_action,
+          stakingVaultAssociatedAddress: legacyVault,
+          payoutConfig: await getPayoutConfigPDA(
+            legacyStaker.publicKey,
+            legacyMint
+          ),
+          payoutOwner: legacyStaker.publicKey,
+          stakerAssociatedAddress: legacyStakerTokenAccount,
+          tokenMint: legacyMint,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [legacyStaker],
+      });
+
+    // Rewards paid before the migration belong to the stakers already weighted
+    await depositRewards('1000');
+    try {
+      await claimRewards();
+      assert.fail('the pool action is still in the old layout');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'AccountDidNotDeserialize');
+    }
+
+    await migratePoolAction();
+    stakePool = await program.account.stakePool.fetch(legacyPda.stake_pool);
+    assert.equal(stakePool.totalWeight.toString(), '10000');
+    let poolAction = await program.account.poolAction.fetch(
+      legacyPda.pool_action
+    );
+    assert.equal(poolAction.tokenAmount.toString(), '5000');
+    assert.equal(
+      poolAction.rewardDebt.toString(),
+      poolAction.tokenAmount
+        .mul(stakePool.rewardPerShare)
+        .div(new anchor.BN(10).pow(new anchor.BN(12)))
+        .toString()
+    );
+
+    // Running it again does not add the weight twice
+    await migratePoolAction();
+    stakePool = await program.account.stakePool.fetch(legacyPda.stake_pool);
+    assert.equal(stakePool.totalWeight.toString(), '10000');
+
+    try {
+      await claimRewards();
+      assert.fail('nothing was distributed since the migration');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'NothingToClaim');
+    }
+
+    // Later rewards are shared with the migrated stake
+    await depositRewards('1000');
+    await claimRewards();
+    assert.equal(await readAccount(legacyStakerTokenAccount), '500');
+  });
+
+  it('Reject Reused Entry Index', async () => {
+    let tokenInterestPda = await getTokenInterestPDA(mintAddress);
+    let pool_count_pda = await getEntryCountPDA(alice.publicKey, mintAddress);
+    let [first_entry_pda, first_entry_bump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [
+          Buffer.from('pool_entry'),
+          alice.publicKey.toBuffer(),
+          mintAddress.toBuffer(),
+          new anchor.BN(1).toArrayLike(Buffer, 'le', 8),
+        ],
+        program.programId
+      );
+
+    try {
+      await program.rpc.performAction(new anchor.BN(1), mintAddress, false, 0, {
+        accounts: {
+          staker: alice.publicKey,
+          currentStakingPool: pda.stake_pool,
+          poolAction: pda.pool_action,
+          lockPoolAction: pda.lock_pool,
+          withdrawPoolAction: pda.withdraw_pool,
+          poolEntry: first_entry_pda,
+          poolCount: pool_count_pda,
+          tokenInterest: tokenInterestPda,
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          stakerAssociatedAddress: aliceTokenAccount,
+          tokenMint: mintAddress,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [alice],
+      });
+      assert.fail('entry index was reused');
+    } catch (err) {
+      assert.notEqual(err.message, 'entry index was reused');
+    }
+
+    // Two stakes, one withdraw request and one claim
+    let last_count = await getLastEntryCount(alice.publicKey, mintAddress);
+    assert.equal(last_count, '4');
+  });
+
+  it('Stake And Request Withdraw', async () => {
+    let [grace, graceTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      2000
+    );
+    let gracePda = await getPdaParams(mintAddress, grace.publicKey);
+
+    let txn = await stakeFor(grace, graceTokenAccount, '2000');
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'Staked');
+
+    let graceBalance = await readAccount(graceTokenAccount);
+    assert.equal(graceBalance, '0');
+
+    // A fresh lot can't be requested before its locking period ends
+    try {
+      await requestWithdrawFor(grace, '500');
+      assert.fail('immature lot should not be requested');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'LockingPeriod');
+    }
+
+    txn = await earlyWithdrawFor(grace, '500');
+    events = await getEvents(txn);
+    assert.equal(events[0].name, 'EarlyWithdrawn');
+
+    let withdrawRequest = await program.account.withdrawRequest.fetch(
+      gracePda.withdraw_pool
+    );
+    assert.equal(withdrawRequest.requestedAmount.toString(), '500');
+
+    let last_count = await getLastEntryCount(grace.publicKey, mintAddress);
+    assert.equal(last_count, '2');
+  });
+
+  it('Early Withdraw With Penalty', async () => {
+    let [henry, henryTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      10000
+    );
+    let henryPda = await getPdaParams(mintAddress, henry.publicKey);
+    let penaltyConfigPda = await getPenaltyConfigPDA(mintAddress);
+
+    // 10% of principal at the start of the lock, sent to the treasury
+    await program.rpc.updatePenaltyConfig(
+      new anchor.BN(1000),
+      true,
+      0,
+      bobAdminTokenAccount,
+      bobAdminTokenAccount,
+      new anchor.BN(0),
+      0,
+      {
+        accounts: {
+          admin: bobAdmin.publicKey,
+          penaltyConfig: penaltyConfigPda,
+          adminConfig: adminConfig,
+          currentStakingPool: pda.stake_pool,
+          tokenMint: mintAddress,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [bobAdmin],
+      }
+    );
+
+    await stakeFor(henry, henryTokenAccount, '10000');
+    let treasuryBefore = await readAccount(bobAdminTokenAccount);
+
+    let txn = await program.rpc.earlyWithdraw(new anchor.BN(10000), {
+      accounts: {
+        staker: henry.publicKey,
+        currentStakingPool: henryPda.stake_pool,
+        poolAction: henryPda.pool_action,
+        lockPoolAction: henryPda.lock_pool,
+        withdrawPoolAction: henryPda.withdraw_pool,
+        poolCount: await getEntryCountPDA(henry.publicKey, mintAddress),
+        poolEntry: await getLatestEntryPDA(henry.publicKey, mintAddress),
+        tokenInterest: await getTokenInterestPDA(mintAddress),
+        penaltyConfig: penaltyConfigPda,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        penaltyDestinationAccount: bobAdminTokenAccount,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [henry],
+    });
+
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'EarlyWithdrawn');
+    let penalty = events[0].data.penaltyAmount.toNumber();
+    // Lock has barely started so nearly the full penalty applies
+    assert.isAtLeast(penalty, 990);
+    assert.isAtMost(penalty, 1000);
+
+    let treasuryAfter = await readAccount(bobAdminTokenAccount);
+    assert.equal(parseInt(treasuryAfter) - parseInt(treasuryBefore), penalty);
+
+    let withdrawRequest = await program.account.withdrawRequest.fetch(
+      henryPda.withdraw_pool
+    );
+    assert.equal(withdrawRequest.requestedAmount.toNumber(), 10000 - penalty);
+  });
+
+  it('Redistribute Early Withdraw Penalty', async () => {
+    let [ivan, ivanTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      10000
+    );
+    let ivanPda = await getPdaParams(mintAddress, ivan.publicKey);
+    let penaltyConfigPda = await getPenaltyConfigPDA(mintAddress);
+
+    // Keep penalties in the pool for the remaining stakers
+    await program.rpc.updatePenaltyConfig(
+      new anchor.BN(1000),
+      true,
+      2,
+      bobAdminTokenAccount,
+      bobAdminTokenAccount,
+      new anchor.BN(0),
+      0,
+      {
+        accounts: {
+          admin: bobAdmin.publicKey,
+          penaltyConfig: penaltyConfigPda,
+          adminConfig: adminConfig,
+          currentStakingPool: pda.stake_pool,
+          tokenMint: mintAddress,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [bobAdmin],
+      }
+    );
+
+    await stakeFor(ivan, ivanTokenAccount, '10000');
+    let stakePoolBefore = await program.account.stakePool.fetch(
+      pda.stake_pool
+    );
+    let vaultBefore = await readAccount(stakingVaultAssociatedAddress);
+
+    let txn = await program.rpc.earlyWithdraw(new anchor.BN(10000), {
+      accounts: {
+        staker: ivan.publicKey,
+        currentStakingPool: ivanPda.stake_pool,
+        poolAction: ivanPda.pool_action,
+        lockPoolAction: ivanPda.lock_pool,
+        withdrawPoolAction: ivanPda.withdraw_pool,
+        poolCount: await getEntryCountPDA(ivan.publicKey, mintAddress),
+        poolEntry: await getLatestEntryPDA(ivan.publicKey, mintAddress),
+        tokenInterest: await getTokenInterestPDA(mintAddress),
+        penaltyConfig: penaltyConfigPda,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        penaltyDestinationAccount: bobAdminTokenAccount,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [ivan],
+    });
+
+    let events = await getEvents(txn);
+    let penalty = events
+      .find((event) => event.name == 'EarlyWithdrawn')
+      .data.penaltyAmount.toString();
+    let distributed = events.find(
+      (event) => event.name == 'RewardsDistributed'
+    );
+    assert.equal(distributed.data.amount.toString(), penalty);
+
+    // Penalty never leaves the vault
+    let vaultAfter = await readAccount(stakingVaultAssociatedAddress);
+    assert.equal(vaultAfter, vaultBefore);
+
+    let stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.equal(
+      stakePool.totalPenaltyDistributed
+        .sub(stakePoolBefore.totalPenaltyDistributed)
+        .toString(),
+      penalty
+    );
+    assert.isTrue(stakePool.rewardPerShare.gt(stakePoolBefore.rewardPerShare));
+    // Ivan exited completely so carries no weight
+    assert.equal(
+      stakePool.totalWeight.toString(),
+      stakePoolBefore.totalWeight.sub(new anchor.BN(10000)).toString()
+    );
+  });
+
+  it('Early Withdraw Penalty Floor With Decimals', async () => {
+    let [kate, kateTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      3000000
+    );
+    let katePda = await getPdaParams(mintAddress, kate.publicKey);
+    let penaltyConfigPda = await getPenaltyConfigPDA(mintAddress);
+
+    const updatePenaltyConfig = async (
+      penaltyDestination: number,
+      penaltyFloor: anchor.BN,
+      amountDecimals = 0
+    ) =>
+      program.rpc.updatePenaltyConfig(
+        new anchor.BN(1000),
+        true,
+        penaltyDestination,
+        bobAdminTokenAccount,
+        bobAdminTokenAccount,
+        penaltyFloor,
+        amountDecimals,
+        {
+          accounts: {
+            admin: bobAdmin.publicKey,
+            penaltyConfig: penaltyConfigPda,
+            adminConfig: adminConfig,
+            currentStakingPool: katePda.stake_pool,
+            tokenMint: mintAddress,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          },
+          signers: [bobAdmin],
+        }
+      );
+
+    // A floor that overflows the mint's base units is rejected
+    try {
+      await updatePenaltyConfig(0, new anchor.BN('18446744073709551615'));
+      assert.fail('overflowing floor should be rejected');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidTokenAmount');
+    }
+
+    // More fractional digits than the pool's recorded decimals
+    try {
+      await updatePenaltyConfig(0, new anchor.BN(1), 7);
+      assert.fail('floor finer than the mint should be rejected');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidTokenAmount');
+    }
+
+    // 1.5 tokens of a 6 decimal mint
+    await updatePenaltyConfig(0, new anchor.BN(15), 1);
+    let penaltyConfig = await program.account.penaltyConfig.fetch(
+      penaltyConfigPda
+    );
+    assert.equal(penaltyConfig.penaltyFloor.toString(), '1500000');
+
+    // One whole token, given in base units
+    await updatePenaltyConfig(0, new anchor.BN(1000000), 6);
+    penaltyConfig = await program.account.penaltyConfig.fetch(
+      penaltyConfigPda
+    );
+    assert.equal(penaltyConfig.penaltyFloor.toString(), '1000000');
+
+    await stakeFor(kate, kateTokenAccount, '3000000');
+    let txn = await program.rpc.earlyWithdraw(new anchor.BN(3000000), {
+      accounts: {
+        staker: kate.publicKey,
+        currentStakingPool: katePda.stake_pool,
+        poolAction: katePda.pool_action,
+        lockPoolAction: katePda.lock_pool,
+        withdrawPoolAction: katePda.withdraw_pool,
+        poolCount: await getEntryCountPDA(kate.publicKey, mintAddress),
+        poolEntry: await getLatestEntryPDA(kate.publicKey, mintAddress),
+        tokenInterest: await getTokenInterestPDA(mintAddress),
+        penaltyConfig: penaltyConfigPda,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        penaltyDestinationAccount: bobAdminTokenAccount,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [kate],
+    });
+
+    // The 10% penalty is below the floor, so the floor applies
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'EarlyWithdrawn');
+    assert.equal(events[0].data.penaltyAmount.toString(), '1000000');
+
+    await clearPenaltyConfig();
+  });
+
+  it('Deposit Rewards Instantly And Streamed', async () => {
+    let [judy, judyTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      10000
+    );
+    let judyPda = await getPdaParams(mintAddress, judy.publicKey);
+    await stakeFor(judy, judyTokenAccount, '10000');
+
+    // Fund the admin with the revenue to share
+    let txMint = new anchor.web3.Transaction();
+    txMint.add(
+      spl.createMintToInstruction(
+        mintAddress,
+        bobAdminTokenAccount,
+        provider.wallet.publicKey,
+        2000,
+        [],
+        spl.TOKEN_PROGRAM_ID
+      )
+    );
+    await provider.sendAndConfirm(txMint);
+
+    const depositRewards = async (amount: string, duration: number) =>
+      program.rpc.depositRewards(
+        new anchor.BN(amount),
+        new anchor.BN(duration),
+        {
+          accounts: {
+            owner: bobAdmin.publicKey,
+            currentStakingPool: pda.stake_pool,
+            adminConfig: adminConfig,
+            stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+            adminAssociatedAddress: bobAdminTokenAccount,
+            tokenMint: mintAddress,
+            tokenProgram: spl.TOKEN_PROGRAM_ID,
+          },
+          signers: [bobAdmin],
+        }
+      );
+
+    // Instant deposit is credited to current stakers right away
+    let stakePoolBefore = await program.account.stakePool.fetch(
+      pda.stake_pool
+    );
+    let txn = await depositRewards('1000', 0);
+    let events = await getEvents(txn);
+    let distributed = events.find(
+      (event) => event.name == 'RewardsDistributed'
+    );
+    assert.equal(distributed.data.source, 1);
+    assert.equal(distributed.data.amount.toString(), '1000');
+    let stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.isTrue(stakePool.rewardPerShare.gt(stakePoolBefore.rewardPerShare));
+
+    // Streamed deposit is held back and released over the period
+    txn = await depositRewards('1000', 2);
+    events = await getEvents(txn);
+    assert.equal(events[0].name, 'RewardsDeposited');
+    stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.isTrue(stakePool.streamRemaining.lte(new anchor.BN(1000)));
+    assert.isTrue(stakePool.rewardEnd.gt(stakePoolBefore.rewardEnd));
+
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+
+    let judyBalanceBefore = await readAccount(judyTokenAccount);
+    txn = await program.rpc.claimRewards({
+      accounts: {
+        staker: judy.publicKey,
+        currentStakingPool: judyPda.stake_pool,
+        poolAction: judyPda.pool_action,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        payoutConfig: await getPayoutConfigPDA(judy.publicKey, mintAddress),
+        payoutOwner: judy.publicKey,
+        stakerAssociatedAddress: judyTokenAccount,
+        tokenMint: mintAddress,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [judy],
+    });
+
+    // The whole stream has ended and been credited
+    stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.equal(stakePool.streamRemaining.toString(), '0');
+    events = await getEvents(txn);
+    let claimed = events.find((event) => event.name == 'RewardsClaimed');
+    let judyBalance = await readAccount(judyTokenAccount);
+    assert.equal(
+      judyBalance - judyBalanceBefore,
+      Number(claimed.data.amount.toString())
+    );
+    assert.isTrue(judyBalance > judyBalanceBefore);
+  });
+
+  it('Reward Schedule Create, Top Up And Extend', async () => {
+    let tokenInterestPda = await getTokenInterestPDA(mintAddress);
+    const scheduleAccounts = {
+      admin: bobAdmin.publicKey,
+      currentStakingPool: pda.stake_pool,
+      adminConfig: adminConfig,
+      tokenInterest: tokenInterestPda,
+      stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+      tokenMint: mintAddress,
+    };
+    let now = await provider.connection.getBlockTime(
+      await provider.connection.getSlot()
+    );
+    let rewardStart = now + 2;
+    let rewardEnd = rewardStart + 20;
+
+    // Refuse a schedule the vault cannot pay for
+    try {
+      await program.rpc.createRewardSchedule(
+        new anchor.BN(1000000000000),
+        new anchor.BN(rewardStart),
+        new anchor.BN(rewardEnd),
+        { accounts: scheduleAccounts, signers: [bobAdmin] }
+      );
+      assert.fail('schedule without funding should fail');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InsufficientRewardFunding');
+    }
+
+    // Cover the pool's liabilities first, then fund the schedule up front
+    let syncTxn = await program.rpc.syncPool({
+      accounts: {
+        currentStakingPool: pda.stake_pool,
+        tokenInterest: tokenInterestPda,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        tokenMint: mintAddress,
+      },
+    });
+    let shortfall = (await getEvents(syncTxn))[0].data.shortfall.toNumber();
+    let funding = shortfall + 5000;
+    let txMint = new anchor.web3.Transaction();
+    txMint.add(
+      spl.createMintToInstruction(
+        mintAddress,
+        bobAdminTokenAccount,
+        provider.wallet.publicKey,
+        funding,
+        [],
+        spl.TOKEN_PROGRAM_ID
+      )
+    );
+    await provider.sendAndConfirm(txMint);
+    await program.rpc.depositToken(new anchor.BN(funding), {
+      accounts: {
+        owner: bobAdmin.publicKey,
+        currentStakingPool: pda.stake_pool,
+        adminConfig: adminConfig,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        adminAssociatedAddress: bobAdminTokenAccount,
+        tokenInterest: tokenInterestPda,
+        tokenMint: mintAddress,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [bobAdmin],
+    });
+
+    let txn = await program.rpc.createRewardSchedule(
+      new anchor.BN(2000),
+      new anchor.BN(rewardStart),
+      new anchor.BN(rewardEnd),
+      { accounts: scheduleAccounts, signers: [bobAdmin] }
+    );
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'RewardScheduleUpdated');
+    assert.equal(events[0].data.rewardStart.toNumber(), rewardStart);
+    assert.equal(events[0].data.streamRemaining.toString(), '2000');
+
+    // Only one schedule can run at a time
+    try {
+      await program.rpc.createRewardSchedule(
+        new anchor.BN(1000),
+        new anchor.BN(rewardStart),
+        new anchor.BN(rewardEnd),
+        { accounts: scheduleAccounts, signers: [bobAdmin] }
+      );
+      assert.fail('overlapping schedule should fail');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'RewardScheduleActive');
+    }
+
+    // A streamed deposit must not replace the scheduled window
+    try {
+      await program.rpc.depositRewards(new anchor.BN(1000), new anchor.BN(5), {
+        accounts: {
+          owner: bobAdmin.publicKey,
+          currentStakingPool: pda.stake_pool,
+          adminConfig: adminConfig,
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          adminAssociatedAddress: bobAdminTokenAccount,
+          tokenMint: mintAddress,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        },
+        signers: [bobAdmin],
+      });
+      assert.fail('streamed deposit over a schedule should fail');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'RewardScheduleActive');
+    }
+    let stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.equal(stakePool.rewardStart.toNumber(), rewardStart);
+    assert.equal(stakePool.rewardEnd.toNumber(), rewardEnd);
+
+    txn = await program.rpc.topUpRewardSchedule(new anchor.BN(1000), {
+      accounts: scheduleAccounts,
+      signers: [bobAdmin],
+    });
+    let rateBeforeExtend = (await getEvents(txn))[0].data.rewardRate;
+
+    txn = await program.rpc.extendRewardSchedule(new anchor.BN(rewardEnd + 20), {
+      accounts: scheduleAccounts,
+      signers: [bobAdmin],
+    });
+    events = await getEvents(txn);
+    assert.equal(events[0].data.rewardEnd.toNumber(), rewardEnd + 20);
+    // Same budget over a longer period emits slower
+    assert.isTrue(events[0].data.rewardRate.lt(rateBeforeExtend));
+
+    stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.equal(stakePool.rewardEnd.toNumber(), rewardEnd + 20);
+    assert.isTrue(stakePool.streamRemaining.lte(new anchor.BN(3000)));
+  });
+
+  it('Interest Budget Runway And Throttle', async () => {
+    let tokenInterestPda = await getTokenInterestPDA(mintAddress);
+    const getRunway = async () => {
+      let txn = await program.rpc.getInterestRunway({
+        accounts: {
+          currentStakingPool: pda.stake_pool,
+          tokenInterest: tokenInterestPda,
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          tokenMint: mintAddress,
+        },
+      });
+      return (await getEvents(txn))[0];
+    };
+
+    // Nothing funded the budget so far, so accrual is paused
+    let runway = await getRunway();
+    assert.equal(runway.name, 'InterestRunway');
+    assert.equal(runway.data.interestBudget.toString(), '0');
+    assert.isTrue(runway.data.interestPaused);
+    let stakePoolBefore = await program.account.stakePool.fetch(
+      pda.stake_pool
+    );
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+    await getRunway();
+    let stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.equal(
+      stakePool.accruedInterest.toString(),
+      stakePoolBefore.accruedInterest.toString()
+    );
+
+    let txMint = new anchor.web3.Transaction();
+    txMint.add(
+      spl.createMintToInstruction(
+        mintAddress,
+        bobAdminTokenAccount,
+        provider.wallet.publicKey,
+        1000000,
+        [],
+        spl.TOKEN_PROGRAM_ID
+      )
+    );
+    await provider.sendAndConfirm(txMint);
+
+    let txn = await program.rpc.fundInterestBudget(new anchor.BN(1000000), {
+      accounts: {
+        owner: bobAdmin.publicKey,
+        currentStakingPool: pda.stake_pool,
+        adminConfig: adminConfig,
+        tokenInterest: tokenInterestPda,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        adminAssociatedAddress: bobAdminTokenAccount,
+        tokenMint: mintAddress,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+      },
+      signers: [bobAdmin],
+    });
+
+    // Funding resumes accrual and the runway covers the budget
+    runway = (await getEvents(txn))[0];
+    assert.isFalse(runway.data.interestPaused);
+    assert.equal(runway.data.interestBudget.toString(), '1000000');
+    assert.isTrue(runway.data.runway.gt(new anchor.BN(0)));
+
+    // The pause is kept in the total so lots skip it whenever they started
+    stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.isFalse(stakePool.interestUnbudgeted);
+    assert.isTrue(stakePool.interestPausedSeconds.gte(new anchor.BN(2)));
+
+    // Runway matches the budget at the current rate and total stake
+    let interestPerYear = runway.data.totalStaked
+      .mul(new anchor.BN(runway.data.interest))
+      .div(new anchor.BN(100));
+    assert.equal(
+      runway.data.runway.toString(),
+      new anchor.BN(1000000).mul(new anchor.BN(31536000)).div(interestPerYear).toString()
+    );
+  });
+
+  it('Liquid Stake Mints Transferable Receipts', async () => {
+    let [kim, kimTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      10000
+    );
+    let [lee] = await createUserAndAssociatedWallet(mintAddress, 0);
+    let [receiptMint] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('receipt_mint'), mintAddress.toBuffer()],
+      program.programId
+    );
+    let kimReceiptAccount = await spl.getAssociatedTokenAddress(
+      receiptMint,
+      kim.publicKey
+    );
+
+    let kimPda = await getPdaParams(mintAddress, kim.publicKey);
+    let txn = await program.rpc.stakeLiquid(new anchor.BN(10000), {
+      accounts: {
+        staker: kim.publicKey,
+        currentStakingPool: pda.stake_pool,
+        poolAction: kimPda.pool_action,
+        poolCount: await getEntryCountPDA(kim.publicKey, mintAddress),
+        poolEntry: await getLatestEntryPDA(kim.publicKey, mintAddress),
+        tokenInterest: await getTokenInterestPDA(mintAddress),
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        stakerAssociatedAddress: kimTokenAccount,
+        receiptMint: receiptMint,
+        stakerReceiptAddress: kimReceiptAccount,
+        tokenMint: mintAddress,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [kim],
+    });
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'LiquidStaked');
+    let receiptAmount = events[0].data.receiptAmount.toNumber();
+    assert.equal(await readAccount(kimReceiptAccount), receiptAmount);
+    // Liquid stake is counted by the receipts a wallet holds, not on its pool action
+    let kimAction = await program.account.poolAction.fetch(kimPda.pool_action);
+    assert.equal(kimAction.positionStaked.toString(), '0');
+
+    // Receipts move between wallets like any SPL token
+    let leeReceiptAccount = await spl.getAssociatedTokenAddress(
+      receiptMint,
+      lee.publicKey
+    );
+    let txTransfer = new anchor.web3.Transaction();
+    txTransfer.add(
+      spl.createAssociatedTokenAccountInstruction(
+        kim.publicKey,
+        leeReceiptAccount,
+        lee.publicKey,
+        receiptMint
+      ),
+      spl.createTransferInstruction(
+        kimReceiptAccount,
+        leeReceiptAccount,
+        kim.publicKey,
+        receiptAmount
+      )
+    );
+    await provider.sendAndConfirm(txTransfer, [kim]);
+
+    let leePda = await getPdaParams(mintAddress, lee.publicKey);
+    let stakePoolBefore = await program.account.stakePool.fetch(
+      pda.stake_pool
+    );
+    txn = await program.rpc.requestWithdrawLiquid(new anchor.BN(receiptAmount), {
+      accounts: {
+        staker: lee.publicKey,
+        currentStakingPool: pda.stake_pool,
+        withdrawPoolAction: leePda.withdraw_pool,
+        poolCount: await getEntryCountPDA(lee.publicKey, mintAddress),
+        poolEntry: await getLatestEntryPDA(lee.publicKey, mintAddress),
+        tokenInterest: await getTokenInterestPDA(mintAddress),
+        receiptMint: receiptMint,
+        stakerReceiptAddress: leeReceiptAccount,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [lee],
+    });
+    events = await getEvents(txn);
+    assert.equal(events[0].name, 'LiquidWithdrawRequested');
+    // Receipts never lose value against the underlying token
+    let amount = events[0].data.amount;
+    assert.isTrue(amount.gte(new anchor.BN(10000)));
+    assert.equal(await readAccount(leeReceiptAccount), '0');
+
+    let withdrawRequest = await program.account.withdrawRequest.fetch(
+      leePda.withdraw_pool
+    );
+    assert.equal(withdrawRequest.requestedAmount.toString(), amount.toString());
+    let stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.equal(
+      stakePool.pendingWithdraw.sub(stakePoolBefore.pendingWithdraw).toString(),
+      amount.toString()
+    );
+  });
+
+  it('NFT Position Transfer And Locked Withdraw', async () => {
+    let [mo, moTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      5000
+    );
+    let [nina, ninaTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      0
+    );
+    let nftMint = anchor.web3.Keypair.generate();
+    let [nftPosition] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('nft_position'), nftMint.publicKey.toBuffer()],
+      program.programId
+    );
+    let moNftAccount = await spl.getAssociatedTokenAddress(
+      nftMint.publicKey,
+      mo.publicKey
+    );
+    let ninaNftAccount = await spl.getAssociatedTokenAddress(
+      nftMint.publicKey,
+      nina.publicKey
+    );
+
+    let moPda = await getPdaParams(mintAddress, mo.publicKey);
+    let ninaPda = await getPdaParams(mintAddress, nina.publicKey);
+    await program.rpc.stakeNftPosition(new anchor.BN(5000), {
+      accounts: {
+        staker: mo.publicKey,
+        currentStakingPool: pda.stake_pool,
+        poolAction: moPda.pool_action,
+        nftMint: nftMint.publicKey,
+        stakerNftAddress: moNftAccount,
+        nftPosition: nftPosition,
+        tokenInterest: await getTokenInterestPDA(mintAddress),
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        stakerAssociatedAddress: moTokenAccount,
+        tokenMint: mintAddress,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [mo, nftMint],
+    });
+    assert.equal(await readAccount(moNftAccount), '1');
+    let position = await program.account.nftPosition.fetch(nftPosition);
+    assert.equal(position.nftMint.toBase58(), nftMint.publicKey.toBase58());
+    assert.equal(position.owner.toBase58(), mo.publicKey.toBase58());
+
+    // Hand the NFT to nina
+    let txTransfer = new anchor.web3.Transaction();
+    txTransfer.add(
+      spl.createAssociatedTokenAccountInstruction(
+        mo.publicKey,
+        ninaNftAccount,
+        nina.publicKey,
+        nftMint.publicKey
+      ),
+      spl.createTransferInstruction(moNftAccount, ninaNftAccount, mo.publicKey, 1)
+    );
+    await provider.sendAndConfirm(txTransfer, [mo]);
+
+    const positionAccounts = {
+      holder: nina.publicKey,
+      currentStakingPool: pda.stake_pool,
+      nftPosition: nftPosition,
+      poolAction: ninaPda.pool_action,
+      holderNftAddress: ninaNftAccount,
+      tokenInterest: await getTokenInterestPDA(mintAddress),
+      stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+      holderAssociatedAddress: ninaTokenAccount,
+      tokenMint: mintAddress,
+      tokenProgram: spl.TOKEN_PROGRAM_ID,
+    };
+
+    // The position has to be rebound before nina can act on it
+    try {
+      await program.rpc.requestWithdrawNftPosition(new anchor.BN(5000), {
+        accounts: positionAccounts,
+        signers: [nina],
+      });
+      assert.fail('unbound holder should not withdraw');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'NotPositionHolder');
+    }
+
+    const transferAccounts = {
+      holder: nina.publicKey,
+      nftPosition: nftPosition,
+      holderNftAddress: ninaNftAccount,
+      previousPoolAction: moPda.pool_action,
+      poolAction: ninaPda.pool_action,
+      systemProgram: anchor.web3.SystemProgram.programId,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    };
+    let txn = await program.rpc.transferPosition({
+      accounts: transferAccounts,
+      signers: [nina],
+    });
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'PositionTransferred');
+    assert.equal(events[0].data.previousOwner.toBase58(), mo.publicKey.toBase58());
+    assert.equal(events[0].data.newOwner.toBase58(), nina.publicKey.toBase58());
+
+    // The position now counts against nina's limit instead of mo's
+    let moAction = await program.account.poolAction.fetch(moPda.pool_action);
+    assert.equal(moAction.positionStaked.toString(), '0');
+    let ninaAction = await program.account.poolAction.fetch(ninaPda.pool_action);
+    assert.equal(ninaAction.positionStaked.toString(), '5000');
+
+    // Rebinding to the current holder would count the position twice
+    try {
+      await program.rpc.transferPosition({
+        accounts: { ...transferAccounts, previousPoolAction: ninaPda.pool_action },
+        signers: [nina],
+      });
+      assert.fail('the position is already bound to nina');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'PositionAlreadyBound');
+    }
+    ninaAction = await program.account.poolAction.fetch(ninaPda.pool_action);
+    assert.equal(ninaAction.positionStaked.toString(), '5000');
+
+    // A position has no early exit, it stays locked for the full period
+    try {
+      await program.rpc.requestWithdrawNftPosition(new anchor.BN(5000), {
+        accounts: positionAccounts,
+        signers: [nina],
+      });
+      assert.fail('immature position should not be requested');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'LockingPeriod');
+    }
+    try {
+      await program.rpc.claimNftPosition(new anchor.BN(5000), {
+        accounts: positionAccounts,
+        signers: [nina],
+      });
+      assert.fail('nothing was requested');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'NotEnoughToken');
+    }
+    assert.equal(await readAccount(ninaTokenAccount), '0');
+
+    position = await program.account.nftPosition.fetch(nftPosition);
+    assert.equal(position.amount.toString(), '5000');
+    assert.equal(position.requestedAmount.toString(), '0');
+  });
+
+  it('Stake For Another Wallet With Lock Date', async () => {
+    let [oscar, oscarTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      3000
+    );
+    let [pat] = await createUserAndAssociatedWallet(mintAddress, 0);
+    let patPda = await getPdaParams(mintAddress, pat.publicKey);
+    let now = await provider.connection.getBlockTime(
+      await provider.connection.getSlot()
+    );
+    let lockUntil = now + 3600;
+    let patEntry = await getLatestEntryPDA(pat.publicKey, mintAddress);
+
+    // A lock is capped at one year ahead
+    try {
+      await stakeForBeneficiary(
+        oscar,
+        oscarTokenAccount,
+        pat.publicKey,
+        '3000',
+        now + 2 * 31536000
+      );
+      assert.fail('lock beyond a year should fail');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidLockDate');
+    }
+
+    // Pat has to co-sign a locked gift
+    try {
+      await stakeForBeneficiary(
+        oscar,
+        oscarTokenAccount,
+        pat.publicKey,
+        '3000',
+        lockUntil
+      );
+      assert.fail('unsigned locked gift should fail');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'GiftLockNotAccepted');
+    }
+
+    let txn = await stakeForBeneficiary(
+      oscar,
+      oscarTokenAccount,
+      pat.publicKey,
+      '3000',
+      lockUntil,
+      pat
+    );
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'StakedFor');
+    assert.equal(events[0].data.beneficiary.toBase58(), pat.publicKey.toBase58());
+    assert.equal(events[0].data.lockedUntil.toNumber(), lockUntil);
+
+    // The position belongs to pat, the funder only paid for it
+    assert.equal(await readAccount(oscarTokenAccount), '0');
+    let poolAction = await program.account.poolAction.fetch(patPda.pool_action);
+    assert.equal(poolAction.tokenAmount.toString(), '3000');
+    let lockPool = await program.account.lockedPool.fetch(patPda.lock_pool);
+    assert.equal(lockPool.lockedAmount[0].toString(), '3000');
+    assert.equal(lockPool.lockedUntil[0].toNumber(), lockUntil);
+    let entry = await getEntryData(patEntry);
+    assert.equal(entry.staker.toBase58(), pat.publicKey.toBase58());
+
+    // Gifted stake cannot be withdrawn before the lock date
+    try {
+      await requestWithdrawFor(pat, '3000');
+      assert.fail('locked gift should not be withdrawable');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'StakeLockedUntil');
+    }
+  });
+
+  it('Gift Lock Leaves The Beneficiary Own Lots Withdrawable', async () => {
+    let [rita, ritaTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      1000
+    );
+    let [sam, samTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      500
+    );
+    let ritaPda = await getPdaParams(mintAddress, rita.publicKey);
+    let now = await provider.connection.getBlockTime(
+      await provider.connection.getSlot()
+    );
+
+    await stakeFor(rita, ritaTokenAccount, '1000');
+    // A third party gifts rita a locked lot
+    await stakeForBeneficiary(
+      sam,
+      samTokenAccount,
+      rita.publicKey,
+      '500',
+      now + 3600,
+      rita
+    );
+
+    let lockPool = await program.account.lockedPool.fetch(ritaPda.lock_pool);
+    assert.equal(lockPool.lockedUntil[0].toNumber(), 0);
+    assert.equal(lockPool.lockedUntil[1].toNumber(), now + 3600);
+
+    // Rita's own lot is still hers to withdraw early
+    await earlyWithdrawFor(rita, '1000');
+    let withdrawRequest = await program.account.withdrawRequest.fetch(
+      ritaPda.withdraw_pool
+    );
+    assert.equal(withdrawRequest.requestedAmount.toString(), '1000');
+
+    // Only the gifted lot waits for its lock date
+    try {
+      await earlyWithdrawFor(rita, '500');
+      assert.fail('locked gift should not be withdrawable');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'StakeLockedUntil');
+    }
+    lockPool = await program.account.lockedPool.fetch(ritaPda.lock_pool);
+    assert.equal(lockPool.lockedAmount[1].toString(), '500');
+  });
+
+  it('Claim To Registered Payout Address', async () => {
+    let [quinn, quinnTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      3000
+    );
+    let [cold, coldTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      0
+    );
+    let quinnPda = await getPdaParams(mintAddress, quinn.publicKey);
+    let payoutConfigPda = await getPayoutConfigPDA(quinn.publicKey, mintAddress);
+    await stakeFor(quinn, quinnTokenAccount, '3000');
+    await earlyWithdrawFor(quinn, '3000');
+
+    const setPayoutAddress = async (
+      payoutOwner: anchor.web3.PublicKey,
+      payoutAccount: anchor.web3.PublicKey
+    ) =>
+      program.rpc.setPayoutAddress({
+        accounts: {
+          staker: quinn.publicKey,
+          currentStakingPool: quinnPda.stake_pool,
+          payoutConfig: payoutConfigPda,
+          payoutOwner: payoutOwner,
+          payoutAssociatedAddress: payoutAccount,
+          tokenMint: mintAddress,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [quinn],
+      });
+    const claimTo = async (
+      payoutOwner: anchor.web3.PublicKey,
+      payoutAccount: anchor.web3.PublicKey
+    ) =>
+      program.rpc.claimWithdraw(new anchor.BN(1000), 0, {
+        accounts: {
+          staker: quinn.publicKey,
+          tokenMint: mintAddress,
+          currentStakingPool: quinnPda.stake_pool,
+          withdrawPoolAction: quinnPda.withdraw_pool,
+          poolEntry: await getLatestEntryPDA(quinn.publicKey, mintAddress),
+          poolCount: await getEntryCountPDA(quinn.publicKey, mintAddress),
+          payoutConfig: payoutConfigPda,
+          payoutOwner: payoutOwner,
+          stakerAssociatedAddress: payoutAccount,
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [quinn],
+      });
+
+    // The first payout address applies at once
+    await setPayoutAddress(cold.publicKey, coldTokenAccount);
+    try {
+      await claimTo(quinn.publicKey, quinnTokenAccount);
+      assert.fail('claim should go to the payout address');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidPayoutAddress');
+    }
+    await claimTo(cold.publicKey, coldTokenAccount);
+    assert.equal(await readAccount(coldTokenAccount), '1000');
+
+    // Rewards follow the same payout address
+    try {
+      await program.rpc.claimRewards({
+        accounts: {
+          staker: quinn.publicKey,
+          currentStakingPool: quinnPda.stake_pool,
+          poolAction: quinnPda.pool_action,
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          payoutConfig: payoutConfigPda,
+          payoutOwner: quinn.publicKey,
+          stakerAssociatedAddress: quinnTokenAccount,
+          tokenMint: mintAddress,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [quinn],
+      });
+      assert.fail('rewards should go to the payout address');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidPayoutAddress');
+    }
+
+    // A negative delay is rejected
+    try {
+      await program.rpc.updatePayoutDelay(new anchor.BN(-1), {
+        accounts: {
+          admin: bobAdmin.publicKey,
+          currentStakingPool: pda.stake_pool,
+          adminConfig: adminConfig,
+          tokenMint: mintAddress,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [bobAdmin],
+      });
+      assert.fail('negative delay should fail');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidPayoutDelay');
+    }
+
+    // Later changes wait for the pool's delay
+    await program.rpc.updatePayoutDelay(new anchor.BN(3600), {
+      accounts: {
+        admin: bobAdmin.publicKey,
+        currentStakingPool: pda.stake_pool,
+        adminConfig: adminConfig,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [bobAdmin],
+    });
+    let txn = await setPayoutAddress(quinn.publicKey, quinnTokenAccount);
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'PayoutAddressUpdated');
+    let payoutConfig = await program.account.payoutConfig.fetch(payoutConfigPda);
+    assert.equal(
+      payoutConfig.pendingPayoutAddress.toBase58(),
+      quinnTokenAccount.toBase58()
+    );
+    assert.equal(
+      payoutConfig.payoutAddress.toBase58(),
+      coldTokenAccount.toBase58()
+    );
+    try {
+      await claimTo(quinn.publicKey, quinnTokenAccount);
+      assert.fail('pending payout address should not apply yet');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidPayoutAddress');
+    }
+    await claimTo(cold.publicKey, coldTokenAccount);
+    assert.equal(await readAccount(coldTokenAccount), '2000');
+
+    await program.rpc.updatePayoutDelay(new anchor.BN(0), {
+      accounts: {
+        admin: bobAdmin.publicKey,
+        currentStakingPool: pda.stake_pool,
+        adminConfig: adminConfig,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [bobAdmin],
+    });
+  });
+
+  it('Claim Recreates A Closed Token Account', async () => {
+    let [rita, ritaTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      2000
+    );
+    let ritaPda = await getPdaParams(mintAddress, rita.publicKey);
+    await stakeFor(rita, ritaTokenAccount, '2000');
+    await earlyWithdrawFor(rita, '2000');
+
+    // Close the emptied token account after staking
+    let txClose = new anchor.web3.Transaction();
+    txClose.add(
+      spl.createCloseAccountInstruction(
+        ritaTokenAccount,
+        rita.publicKey,
+        rita.publicKey
+      )
+    );
+    await provider.sendAndConfirm(txClose, [rita]);
+    assert.isNull(await provider.connection.getAccountInfo(ritaTokenAccount));
+
+    await program.rpc.claimWithdraw(new anchor.BN(2000), 0, {
+      accounts: {
+        staker: rita.publicKey,
+        tokenMint: mintAddress,
+        currentStakingPool: ritaPda.stake_pool,
+        withdrawPoolAction: ritaPda.withdraw_pool,
+        poolEntry: await getLatestEntryPDA(rita.publicKey, mintAddress),
+        poolCount: await getEntryCountPDA(rita.publicKey, mintAddress),
+        payoutConfig: await getPayoutConfigPDA(rita.publicKey, mintAddress),
+        payoutOwner: rita.publicKey,
+        stakerAssociatedAddress: ritaTokenAccount,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [rita],
+    });
+    assert.equal(await readAccount(ritaTokenAccount), '2000');
+
+    // Only the canonical associated account is accepted
+    let [, otherTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      0
+    );
+    try {
+      await program.rpc.claimWithdraw(new anchor.BN(0), 0, {
+        accounts: {
+          staker: rita.publicKey,
+          tokenMint: mintAddress,
+          currentStakingPool: ritaPda.stake_pool,
+          withdrawPoolAction: ritaPda.withdraw_pool,
+          poolEntry: await getLatestEntryPDA(rita.publicKey, mintAddress),
+          poolCount: await getEntryCountPDA(rita.publicKey, mintAddress),
+          payoutConfig: await getPayoutConfigPDA(rita.publicKey, mintAddress),
+          payoutOwner: rita.publicKey,
+          stakerAssociatedAddress: otherTokenAccount,
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [rita],
+      });
+      assert.fail('non-canonical account should be rejected');
+    } catch (err) {
+      assert.isDefined(err);
+      assert.notEqual(err.message, 'non-canonical account should be rejected');
+    }
+  });
+
+  it('Native SOL Pool Wraps And Unwraps', async () => {
+    let [sam] = await createUserAndAssociatedWallet(mintAddress, 0);
+    let nativeMint = spl.NATIVE_MINT;
+    let samPda = await getPdaParams(nativeMint, sam.publicKey);
+    let solVault = await spl.getAssociatedTokenAddress(
+      nativeMint,
+      samPda.stake_pool,
+      true
+    );
+    let stakeLamports = anchor.web3.LAMPORTS_PER_SOL;
+
+    await createPool(nativeMint);
+    await program.rpc.stakeSol(new anchor.BN(stakeLamports), {
+      accounts: {
+        staker: sam.publicKey,
+        currentStakingPool: samPda.stake_pool,
+        poolAction: samPda.pool_action,
+        lockPoolAction: samPda.lock_pool,
+        poolCount: await getEntryCountPDA(sam.publicKey, nativeMint),
+        poolEntry: await getLatestEntryPDA(sam.publicKey, nativeMint),
+        tokenInterest: await getTokenInterestPDA(nativeMint),
+        stakingVaultAssociatedAddress: solVault,
+        tokenMint: nativeMint,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [sam],
+    });
+    // The vault holds the lamports as wSOL
+    assert.equal(await readAccount(solVault), stakeLamports.toString());
+
+    // The fresh lot leaves early, without a penalty on this pool
+    let adminSolAccount = await spl.getAssociatedTokenAddress(
+      nativeMint,
+      bobAdmin.publicKey
+    );
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        spl.createAssociatedTokenAccountInstruction(
+          provider.wallet.publicKey,
+          adminSolAccount,
+          bobAdmin.publicKey,
+          nativeMint
+        )
+      )
+    );
+    await clearPenaltyConfig(nativeMint);
+    await earlyWithdrawFor(
+      sam,
+      stakeLamports.toString(),
+      nativeMint,
+      adminSolAccount
+    );
+
+    let [unwrapAccount] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('unwrap'), sam.publicKey.toBuffer(), nativeMint.toBuffer()],
+      program.programId
+    );
+    let balanceBefore = await provider.connection.getBalance(sam.publicKey);
+    await program.rpc.claimWithdrawSol(new anchor.BN(stakeLamports), {
+      accounts: {
+        staker: sam.publicKey,
+        currentStakingPool: samPda.stake_pool,
+        withdrawPoolAction: samPda.withdraw_pool,
+        poolCount: await getEntryCountPDA(sam.publicKey, nativeMint),
+        poolEntry: await getLatestEntryPDA(sam.publicKey, nativeMint),
+        payoutConfig: await getPayoutConfigPDA(sam.publicKey, nativeMint),
+        stakingVaultAssociatedAddress: solVault,
+        unwrapAssociatedAddress: unwrapAccount,
+        tokenMint: nativeMint,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [sam],
+    });
+
+    // Paid back in lamports, less fees and the new entry's rent
+    let balance = await provider.connection.getBalance(sam.publicKey);
+    assert.isTrue(balance - balanceBefore > stakeLamports * 0.99);
+    assert.equal(await readAccount(solVault), '0');
+    assert.isNull(await provider.connection.getAccountInfo(unwrapAccount));
+  });
+
+  it('Stake Credits The Amount Received By The Vault', async () => {
+    const updateTransferTolerance = async (tolerance: number) =>
+      program.rpc.updateTransferTolerance(new anchor.BN(tolerance), {
+        accounts: {
+          admin: bobAdmin.publicKey,
+          currentStakingPool: pda.stake_pool,
+          adminConfig: adminConfig,
+          tokenMint: mintAddress,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [bobAdmin],
+      });
+
+    try {
+      await updateTransferTolerance(10001);
+      assert.fail('tolerance above 100% should be rejected');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidTransferTolerance');
+    }
+    await updateTransferTolerance(50);
+    let stakePool = await program.account.stakePool.fetch(pda.stake_pool);
+    assert.equal(stakePool.transferTolerance.toString(), '50');
+
+    // A plain SPL mint delivers the full amount, so that is what gets booked
+    let [sam, samTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      2500
+    );
+    let samPda = await getPdaParams(mintAddress, sam.publicKey);
+    let vaultBefore = new anchor.BN(await readAccount(stakingVaultAssociatedAddress));
+    let txn = await stakeFor(sam, samTokenAccount, '2500');
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'Staked');
+    assert.equal(events[0].data.amount.toString(), '2500');
+    let vaultAfter = new anchor.BN(await readAccount(stakingVaultAssociatedAddress));
+    assert.equal(vaultAfter.sub(vaultBefore).toString(), '2500');
+    let poolAction = await program.account.poolAction.fetch(samPda.pool_action);
+    assert.equal(poolAction.tokenAmount.toString(), '2500');
+
+    await updateTransferTolerance(0);
+  });
+
+  it('Basket Pool Normalizes Stablecoins, Caps Each Mint And Budgets Interest', async () => {
+    // A second stablecoin with 9 decimals next to the 6 decimal test mint
+    let wideMint = await createMint(9);
+    let [tina, tinaTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      2000000
+    );
+    let [basketPool] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('basket_pool')],
+      program.programId
+    );
+    let [basketPosition] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('basket_position'), tina.publicKey.toBuffer()],
+      program.programId
+    );
+
+    const basketAccounts = async (mint: anchor.web3.PublicKey) => {
+      let [basketMint] = await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from('basket_mint'), mint.toBuffer()],
+        program.programId
+      );
+      return {
+        basketPool: basketPool,
+        basketMint: basketMint,
+        basketVault: await spl.getAssociatedTokenAddress(
+          mint,
+          basketPool,
+          true
+        ),
+        tokenMint: mint,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      };
+    };
+    const setBasketMint = async (mint: anchor.web3.PublicKey, cap: number) =>
+      program.rpc.setBasketMint(new anchor.BN(cap), true, {
+        accounts: {
+          admin: bobAdmin.publicKey,
+          adminConfig: adminConfig,
+          ...(await basketAccounts(mint)),
+        },
+        signers: [bobAdmin],
+      });
+    const basketAction = async (
+      method: 'stakeBasket' | 'withdrawBasket',
+      mint: anchor.web3.PublicKey,
+      amount: anchor.BN
+    ) =>
+      program.rpc[method](amount, {
+        accounts: {
+          staker: tina.publicKey,
+          basketPosition: basketPosition,
+          stakerAssociatedAddress: await spl.getAssociatedTokenAddress(
+            mint,
+            tina.publicKey
+          ),
+          ...(await basketAccounts(mint)),
+        },
+        signers: [tina],
+      });
+
+    // Caps are given in whole tokens of the 6 decimal basket unit
+    await setBasketMint(mintAddress, 1);
+    await setBasketMint(wideMint, 1000);
+    let [basketMint] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('basket_mint'), mintAddress.toBuffer()],
+      program.programId
+    );
+    let basketMintAccount = await program.account.basketMint.fetch(basketMint);
+    assert.equal(basketMintAccount.cap.toString(), '1000000');
+
+    await basketAction('stakeBasket', mintAddress, new anchor.BN(1000000));
+    try {
+      await basketAction('stakeBasket', mintAddress, new anchor.BN(1));
+      assert.fail('stake over the mint cap should fail');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'BasketMintCapExceeded');
+    }
+
+    // Two whole tokens of the 9 decimal mint count as 2000000 basket units
+    let tinaWideAccount = await spl.getAssociatedTokenAddress(
+      wideMint,
+      tina.publicKey
+    );
+    let fundTx = new anchor.web3.Transaction();
+    fundTx.add(
+      spl.createAssociatedTokenAccountInstruction(
+        provider.wallet.publicKey,
+        tinaWideAccount,
+        tina.publicKey,
+        wideMint
+      ),
+      spl.createMintToInstruction(
+        wideMint,
+        tinaWideAccount,
+        provider.wallet.publicKey,
+        2000000000
+      )
+    );
+    await provider.sendAndConfirm(fundTx);
+    let txn = await basketAction(
+      'stakeBasket',
+      wideMint,
+      new anchor.BN(2000000000)
+    );
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'BasketStaked');
+    assert.equal(events[0].data.normalizedAmount.toString(), '2000000');
+    let position = await program.account.basketPosition.fetch(basketPosition);
+    assert.equal(position.amount.toString(), '3000000');
+
+    // The 6 decimal deposit is withdrawn in the 9 decimal mint
+    await basketAction('withdrawBasket', wideMint, new anchor.BN(1500000));
+    assert.equal(await readAccount(tinaWideAccount), '1500000000');
+
+    // Each mint only pays out what its own vault holds
+    try {
+      await basketAction('withdrawBasket', mintAddress, new anchor.BN(1500000));
+      assert.fail('withdraw above the mint liquidity should fail');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InsufficientBasketLiquidity');
+    }
+    await basketAction('withdrawBasket', mintAddress, new anchor.BN(1000000));
+    assert.equal(await readAccount(tinaTokenAccount), '2000000');
+    position = await program.account.basketPosition.fetch(basketPosition);
+    assert.equal(position.amount.toString(), '500000');
+
+    // Interest only accrues while the basket budget covers it
+    const updateBasketInterest = async (interest: number) =>
+      program.rpc.updateBasketInterest(interest, {
+        accounts: {
+          admin: bobAdmin.publicKey,
+          basketPool: basketPool,
+          adminConfig: adminConfig,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [bobAdmin],
+      });
+    let adminWideAccount = await spl.getAssociatedTokenAddress(
+      wideMint,
+      bobAdmin.publicKey
+    );
+    fundTx = new anchor.web3.Transaction();
+    fundTx.add(
+      spl.createAssociatedTokenAccountInstruction(
+        provider.wallet.publicKey,
+        adminWideAccount,
+        bobAdmin.publicKey,
+        wideMint
+      ),
+      spl.createMintToInstruction(
+        wideMint,
+        adminWideAccount,
+        provider.wallet.publicKey,
+        50000
+      ),
+      spl.createMintToInstruction(
+        wideMint,
+        tinaWideAccount,
+        provider.wallet.publicKey,
+        new anchor.BN('900000000000000000')
+      )
+    );
+    await provider.sendAndConfirm(fundTx);
+
+    await updateBasketInterest(100);
+    await basketAction(
+      'stakeBasket',
+      wideMint,
+      new anchor.BN('900000000000000000')
+    );
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+
+    // Nothing accrues before the budget is funded
+    await updateBasketInterest(100);
+    let basketPoolAccount = await program.account.basketPool.fetch(basketPool);
+    assert.equal(basketPoolAccount.interestIndex.toString(), '0');
+
+    // 50 basket units of budget, paid in the 9 decimal mint
+    let { basketMint: wideBasketMint, basketVault: wideBasketVault } =
+      await basketAccounts(wideMint);
+    txn = await program.rpc.fundBasketInterest(new anchor.BN(50000), {
+      accounts: {
+        admin: bobAdmin.publicKey,
+        basketPool: basketPool,
+        basketMint: wideBasketMint,
+        basketVault: wideBasketVault,
+        adminAssociatedAddress: adminWideAccount,
+        adminConfig: adminConfig,
+        tokenMint: wideMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+      },
+      signers: [bobAdmin],
+    });
+    events = await getEvents(txn);
+    assert.equal(events[0].name, 'BasketInterestFunded');
+    assert.equal(events[0].data.normalizedAmount.toString(), '50');
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+
+    // The rate change checkpoints accrual, which the budget caps
+    await updateBasketInterest(0);
+    basketPoolAccount = await program.account.basketPool.fetch(basketPool);
+    assert.equal(basketPoolAccount.interestBudget.toString(), '0');
+    assert.isTrue(basketPoolAccount.interestIndex.gtn(0));
+
+    txn = await basketAction('withdrawBasket', wideMint, new anchor.BN(1));
+    events = await getEvents(txn);
+    assert.equal(events[0].data.interestAmount.toString(), '1');
+    position = await program.account.basketPosition.fetch(basketPosition);
+    assert.isTrue(position.accruedInterest.lten(49));
+    assert.equal(position.amount.toString(), '900500000');
+  });
+
+  it('Stake Limits And Remaining Capacity', async () => {
+    let [uma, umaTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      2000000000
+    );
+    const updateStakeLimits = async (
+      minStake: number,
+      maxStakePerUser: number,
+      poolCapacity: number,
+      amountDecimals = 0
+    ) =>
+      program.rpc.updateStakeLimits(
+        new anchor.BN(minStake),
+        new anchor.BN(maxStakePerUser),
+        new anchor.BN(poolCapacity),
+        amountDecimals,
+        {
+          accounts: {
+            admin: bobAdmin.publicKey,
+            currentStakingPool: pda.stake_pool,
+            adminConfig: adminConfig,
+            tokenMint: mintAddress,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          },
+          signers: [bobAdmin],
+        }
+      );
+    const expectError = async (amount: string, code: string) => {
+      try {
+        await stakeFor(uma, umaTokenAccount, amount);
+        assert.fail(`stake of ${amount} should fail`);
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, code);
+      }
+    };
+
+    try {
+      await updateStakeLimits(3, 2, 0);
+      assert.fail('minimum above maximum should be rejected');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidStakeLimits');
+    }
+
+    try {
+      await updateStakeLimits(1, 2, 0, 7);
+      assert.fail('limits finer than the mint should be rejected');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'InvalidTokenAmount');
+    }
+
+    // Limits of 1 and 2.5 tokens of the 6 decimal mint
+    await updateStakeLimits(10, 25, 0, 1);
+    await expectError('0', 'StakeBelowMinimum');
+    await expectError('500000', 'StakeBelowMinimum');
+    await stakeFor(uma, umaTokenAccount, '1500000');
+    await expectError('1000001', 'StakeAboveUserMaximum');
+
+    // Liquid and NFT stakes count against the same per-user maximum
+    await updateStakeLimits(0, 25, 0, 1);
+    let umaPda = await getPdaParams(mintAddress, uma.publicKey);
+    let [receiptMint] = await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from('receipt_mint'), mintAddress.toBuffer()],
+      program.programId
+    );
+    const stakeLiquid = async (amount: number) =>
+      program.rpc.stakeLiquid(new anchor.BN(amount), {
+        accounts: {
+          staker: uma.publicKey,
+          currentStakingPool: pda.stake_pool,
+          poolAction: umaPda.pool_action,
+          poolCount: await getEntryCountPDA(uma.publicKey, mintAddress),
+          poolEntry: await getLatestEntryPDA(uma.publicKey, mintAddress),
+          tokenInterest: await getTokenInterestPDA(mintAddress),
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          stakerAssociatedAddress: umaTokenAccount,
+          receiptMint: receiptMint,
+          stakerReceiptAddress: await spl.getAssociatedTokenAddress(
+            receiptMint,
+            uma.publicKey
+          ),
+          tokenMint: mintAddress,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [uma],
+      });
+    const stakeNftPosition = async (amount: number) => {
+      let nftMint = anchor.web3.Keypair.generate();
+      let [nftPosition] = await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from('nft_position'), nftMint.publicKey.toBuffer()],
+        program.programId
+      );
+      return program.rpc.stakeNftPosition(new anchor.BN(amount), {
+        accounts: {
+          staker: uma.publicKey,
+          currentStakingPool: pda.stake_pool,
+          poolAction: umaPda.pool_action,
+          nftMint: nftMint.publicKey,
+          stakerNftAddress: await spl.getAssociatedTokenAddress(
+            nftMint.publicKey,
+            uma.publicKey
+          ),
+          nftPosition: nftPosition,
+          tokenInterest: await getTokenInterestPDA(mintAddress),
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          stakerAssociatedAddress: umaTokenAccount,
+          tokenMint: mintAddress,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [uma, nftMint],
+      });
+    };
+    for (const stake of [stakeLiquid, stakeNftPosition]) {
+      try {
+        await stake(1000001);
+        assert.fail('stake above the user maximum should fail');
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, 'StakeAboveUserMaximum');
+      }
+    }
+    await stakeLiquid(500000);
+    await stakeNftPosition(500000);
+    let umaAction = await program.account.poolAction.fetch(umaPda.pool_action);
+    assert.equal(umaAction.positionStaked.toString(), '500000');
+    await expectError('500001', 'StakeAboveUserMaximum');
+
+    // The receipts uma holds count when adding liquid stake
+    try {
+      await stakeLiquid(250000);
+      assert.fail('held receipts count against the user maximum');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'StakeAboveUserMaximum');
+    }
+
+    await updateStakeLimits(0, 0, 1000);
+    let txn = await program.rpc.getStakeCapacity({
+      accounts: {
+        currentStakingPool: pda.stake_pool,
+        tokenInterest: await getTokenInterestPDA(mintAddress),
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        tokenMint: mintAddress,
+      },
+    });
+    let capacity = (await getEvents(txn))[0];
+    assert.equal(capacity.name, 'StakeCapacity');
+    assert.equal(capacity.data.poolCapacity.toString(), '1000000000');
+    assert.equal(
+      capacity.data.remainingCapacity.toString(),
+      capacity.data.poolCapacity.sub(capacity.data.totalStaked).toString()
+    );
+    await expectError(
+      capacity.data.remainingCapacity.addn(1).toString(),
+      'PoolCapacityExceeded'
+    );
+
+    await updateStakeLimits(0, 0, 0);
+  });
+
+  it('Stake More Than 50 Times', async () => {
+    let stake_count = 55;
+    let [dave, daveTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      stake_count * 1000
+    );
+    let davePda = await getPdaParams(mintAddress, dave.publicKey);
+    let initialVaultBalance = await readAccount(stakingVaultAssociatedAddress);
+
+    for (let i = 0; i < stake_count; i++) {
+      await performActionFor(dave, daveTokenAccount, '1000', true);
+    }
+
+    let lockPool = await program.account.lockedPool.fetch(davePda.lock_pool);
+    assert.equal(lockPool.lockedAmount.length, stake_count);
+    assert.equal(lockPool.lockedStartTime.length, stake_count);
+
+    let daveBalance = await readAccount(daveTokenAccount);
+    assert.equal(daveBalance, '0');
+
+    let stakingVaultBalance = await readAccount(stakingVaultAssociatedAddress);
+    assert.equal(
+      stakingVaultBalance,
+      (parseInt(initialVaultBalance) + stake_count * 1000).toString()
+    );
+  });
+
+  it('Merge Lots Compacts Holes', async () => {
+    [erin, erinTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      3000
+    );
+    let erinPda = await getPdaParams(mintAddress, erin.publicKey);
+    let tokenInterestPda = await getTokenInterestPDA(mintAddress);
+
+    await performActionFor(erin, erinTokenAccount, '1000', true);
+    await performActionFor(erin, erinTokenAccount, '1000', true);
+    await performActionFor(erin, erinTokenAccount, '1000', true);
+    // Consumes the first two lots, leaving zeroed holes
+    await earlyWithdrawFor(erin, '2000');
+
+    let lockPool = await program.account.lockedPool.fetch(erinPda.lock_pool);
+    assert.equal(lockPool.lockedAmount.length, 3);
+    let keptStartTime = lockPool.lockedStartTime[2].toString();
+    let keptInterestStart = lockPool.lockedInterestStart[2].toString();
+    let poolActionBefore = await program.account.poolAction.fetch(
+      erinPda.pool_action
+    );
+
+    let txn = await program.rpc.mergeLots({
+      accounts: {
+        staker: erin.publicKey,
+        currentStakingPool: erinPda.stake_pool,
+        poolAction: erinPda.pool_action,
+        lockPoolAction: erinPda.lock_pool,
+        tokenInterest: tokenInterestPda,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      signers: [erin],
+    });
+
+    // Remaining lot is still locked so it is kept as is
+    lockPool = await program.account.lockedPool.fetch(erinPda.lock_pool);
+    assert.equal(lockPool.lockedAmount.length, 1);
+    assert.equal(lockPool.lockedAmount[0].toString(), '1000');
+    assert.equal(lockPool.lockedStartTime[0].toString(), keptStartTime);
+    assert.equal(lockPool.lockedInterestStart[0].toString(), keptInterestStart);
+
+    // Nothing was settled, so the weight is unchanged
+    let poolAction = await program.account.poolAction.fetch(erinPda.pool_action);
+    assert.equal(
+      poolAction.tokenAmount.toString(),
+      poolActionBefore.tokenAmount.toString()
+    );
+
+    let events = await getEvents(txn);
+    assert.equal(events[0].name, 'LotsMerged');
+    assert.equal(events[0].data.mergedLots.toString(), '0');
+    assert.equal(events[0].data.interestAmount.toString(), '0');
+  });
+
+  it('Zero-Copy Position Stake And Locked Withdraw Request', async () => {
+    let [frank, frankTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      3000
+    );
+    let frankPda = await getPdaParams(mintAddress, frank.publicKey);
+    let lotPositionPda = await getLotPositionPDA(frank.publicKey, mintAddress);
+    let tokenInterestPda = await getTokenInterestPDA(mintAddress);
+
+    await program.rpc.initPosition({
+      accounts: {
+        staker: frank.publicKey,
+        lotPosition: lotPositionPda,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [frank],
+    });
+
+    for (let i = 0; i < 3; i++) {
+      await program.rpc.stakePosition(new anchor.BN(1000), {
+        accounts: {
+          staker: frank.publicKey,
+          currentStakingPool: frankPda.stake_pool,
+          poolAction: frankPda.pool_action,
+          lotPosition: lotPositionPda,
+          tokenInterest: tokenInterestPda,
+          stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+          stakerAssociatedAddress: frankTokenAccount,
+          tokenMint: mintAddress,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [frank],
+      });
+    }
+
+    let lotPosition = await program.account.lotPosition.fetch(lotPositionPda);
+    assert.equal(lotPosition.owner.toString(), frank.publicKey.toString());
+    assert.equal(lotPosition.lots[2].amount.toString(), '1000');
+    assert.equal(lotPosition.lots[3].flags, 0);
+
+    // Zero-copy lots are only released once their locking period is over
+    try {
+      await program.rpc.requestWithdrawPosition(new anchor.BN(1500), {
+        accounts: {
+          staker: frank.publicKey,
+          currentStakingPool: frankPda.stake_pool,
+          poolAction: frankPda.pool_action,
+          lotPosition: lotPositionPda,
+          withdrawPoolAction: frankPda.withdraw_pool,
+          tokenInterest: tokenInterestPda,
+          tokenMint: mintAddress,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [frank],
+      });
+      assert.fail('immature lots should not be requested');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'LockingPeriod');
+    }
+
+    lotPosition = await program.account.lotPosition.fetch(lotPositionPda);
+    assert.equal(lotPosition.lots[0].amount.toString(), '1000');
+    assert.equal(lotPosition.lots[1].amount.toString(), '1000');
+  });
+
+  it('Migrate LockedPool To Zero-Copy Position', async () => {
+    let erinPda = await getPdaParams(mintAddress, erin.publicKey);
+    let lotPositionPda = await getLotPositionPDA(erin.publicKey, mintAddress);
+
+    await program.rpc.migratePosition({
+      accounts: {
+        staker: erin.publicKey,
+        lotPosition: lotPositionPda,
+        lockPoolAction: erinPda.lock_pool,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [erin],
+    });
+
+    let lotPosition = await program.account.lotPosition.fetch(lotPositionPda);
+    assert.equal(lotPosition.lots[0].amount.toString(), '1000');
+    assert.equal(lotPosition.lots[1].flags, 0);
+
+    let lockPoolInfo = await provider.connection.getAccountInfo(
+      erinPda.lock_pool
+    );
+    assert.isNull(lockPoolInfo);
+  });
+
+  it('Close Recent Entry Fails', async () => {
+    try {
+      await program.rpc.closeEntry(new anchor.BN(1), {
+        accounts: {
+          staker: alice.publicKey,
+          poolEntry: (
+            await getPdaStakingEntries(alice.publicKey, mintAddress)
+          ).entries_pda.slice(-1)[0],
+          tokenMint: mintAddress,
+        },
+        signers: [alice],
+      });
+      assert.fail('recent entry was closed');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'EntryTooRecent');
+    }
+  });
+
+  it('Close Legacy Entry With A One Byte Index', async () => {
+    // Written by the first release at index 1, loaded from tests/fixtures
+    let [legacyEntry] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        Buffer.from('pool_entry'),
+        legacyStaker.publicKey.toBuffer(),
+        legacyMint.toBuffer(),
+        Buffer.from([1]),
+      ],
+      program.programId
+    );
+    const closeEntry = async (index: number) =>
+      program.rpc.closeEntry(new anchor.BN(index), {
+        accounts: {
+          staker: legacyStaker.publicKey,
+          poolEntry: legacyEntry,
+          tokenMint: legacyMint,
+        },
+        signers: [legacyStaker],
+      });
+
+    try {
+      await closeEntry(2);
+      assert.fail('the entry is not at index 2');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'ConstraintSeeds');
+    }
+
+    let balanceBefore = await provider.connection.getBalance(
+      legacyStaker.publicKey
+    );
+    await closeEntry(1);
+    let balanceAfter = await provider.connection.getBalance(
+      legacyStaker.publicKey
+    );
+    assert.isAbove(balanceAfter, balanceBefore);
+    assert.isNull(await provider.connection.getAccountInfo(legacyEntry));
+  });
+
+  it('Close Position', async () => {
+    let [carol, carolTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      1000000
+    );
+    let carolPda = await getPdaParams(mintAddress, carol.publicKey);
+    let pool_count_pda = await getEntryCountPDA(carol.publicKey, mintAddress);
+
+    const closeAccounts = {
+      staker: carol.publicKey,
+      currentStakingPool: carolPda.stake_pool,
+      poolAction: carolPda.pool_action,
+      lockPoolAction: carolPda.lock_pool,
+      withdrawPoolAction: carolPda.withdraw_pool,
+      lotPosition: await getLotPositionPDA(carol.publicKey, mintAddress),
+      tokenMint: mintAddress,
+    };
+
+    await performActionFor(carol, carolTokenAccount, '1000000', true);
+
+    // Position still holds principal
+    try {
+      await program.rpc.closePosition({
+        accounts: closeAccounts,
+        signers: [carol],
+      });
+      assert.fail('open position was closed');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'PositionNotEmpty');
+    }
+
+    // Exit the position completely
+    await earlyWithdrawFor(carol, '1000000');
+    await program.rpc.claimWithdraw(new anchor.BN(1000000), 0, {
+      accounts: {
+        staker: carol.publicKey,
+        tokenMint: mintAddress,
+        currentStakingPool: carolPda.stake_pool,
+        withdrawPoolAction: carolPda.withdraw_pool,
+        poolEntry: await getLatestEntryPDA(carol.publicKey, mintAddress),
+        poolCount: pool_count_pda,
+        payoutConfig: await getPayoutConfigPDA(carol.publicKey, mintAddress),
+        payoutOwner: carol.publicKey,
+        stakerAssociatedAddress: carolTokenAccount,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [carol],
+    });
+
+    let balanceBefore = await provider.connection.getBalance(carol.publicKey);
+    await program.rpc.closePosition({
+      accounts: closeAccounts,
+      signers: [carol],
+    });
+    let balanceAfter = await provider.connection.getBalance(carol.publicKey);
+    assert.isAbove(balanceAfter, balanceBefore);
+
+    let lockPoolInfo = await provider.connection.getAccountInfo(
+      carolPda.lock_pool
+    );
+    assert.isNull(lockPoolInfo);
+  });
+
+  it('Close Position After Migrating To Zero-Copy', async () => {
+    let [wendy, wendyTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      1000
+    );
+    let wendyPda = await getPdaParams(mintAddress, wendy.publicKey);
+    let lotPositionPda = await getLotPositionPDA(wendy.publicKey, mintAddress);
+
+    await performActionFor(wendy, wendyTokenAccount, '1000', true);
+    await earlyWithdrawFor(wendy, '1000');
+    await program.rpc.claimWithdraw(new anchor.BN(1000), 0, {
+      accounts: {
+        staker: wendy.publicKey,
+        tokenMint: mintAddress,
+        currentStakingPool: wendyPda.stake_pool,
+        withdrawPoolAction: wendyPda.withdraw_pool,
+        poolEntry: await getLatestEntryPDA(wendy.publicKey, mintAddress),
+        poolCount: await getEntryCountPDA(wendy.publicKey, mintAddress),
+        payoutConfig: await getPayoutConfigPDA(wendy.publicKey, mintAddress),
+        payoutOwner: wendy.publicKey,
+        stakerAssociatedAddress: wendyTokenAccount,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [wendy],
+    });
+
+    // Migrating closes the LockedPool, only the zero-copy position is left
+    await program.rpc.migratePosition({
+      accounts: {
+        staker: wendy.publicKey,
+        lotPosition: lotPositionPda,
+        lockPoolAction: wendyPda.lock_pool,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [wendy],
+    });
+    assert.isNull(await provider.connection.getAccountInfo(wendyPda.lock_pool));
+
+    let balanceBefore = await provider.connection.getBalance(wendy.publicKey);
+    await program.rpc.closePosition({
+      accounts: {
+        staker: wendy.publicKey,
+        currentStakingPool: wendyPda.stake_pool,
+        poolAction: wendyPda.pool_action,
+        lockPoolAction: wendyPda.lock_pool,
+        withdrawPoolAction: wendyPda.withdraw_pool,
+        lotPosition: lotPositionPda,
+        tokenMint: mintAddress,
+      },
+      signers: [wendy],
+    });
+    let balanceAfter = await provider.connection.getBalance(wendy.publicKey);
+    assert.isAbove(balanceAfter, balanceBefore);
+
+    // The zero-copy position is closed with the rest
+    assert.isNull(await provider.connection.getAccountInfo(lotPositionPda));
+    assert.isNull(
+      await provider.connection.getAccountInfo(wendyPda.pool_action)
+    );
+  });
+
+  it('Close Position Rejects Zero-Copy Lots', async () => {
+    let [dave, daveTokenAccount] = await createUserAndAssociatedWallet(
+      mintAddress,
+      2000
+    );
+    let davePda = await getPdaParams(mintAddress, dave.publicKey);
+    let lotPositionPda = await getLotPositionPDA(dave.publicKey, mintAddress);
+
+    // Leave the LockedPool and withdraw request empty
+    await performActionFor(dave, daveTokenAccount, '1000', true);
+    await earlyWithdrawFor(dave, '1000');
+    await program.rpc.claimWithdraw(new anchor.BN(1000), 0, {
+      accounts: {
+        staker: dave.publicKey,
+        tokenMint: mintAddress,
+        currentStakingPool: davePda.stake_pool,
+        withdrawPoolAction: davePda.withdraw_pool,
+        poolEntry: await getLatestEntryPDA(dave.publicKey, mintAddress),
+        poolCount: await getEntryCountPDA(dave.publicKey, mintAddress),
+        payoutConfig: await getPayoutConfigPDA(dave.publicKey, mintAddress),
+        payoutOwner: dave.publicKey,
+        stakerAssociatedAddress: daveTokenAccount,
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [dave],
+    });
+
+    // The stake now lives only in the zero-copy position
+    await program.rpc.initPosition({
+      accounts: {
+        staker: dave.publicKey,
+        lotPosition: lotPositionPda,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [dave],
+    });
+    await program.rpc.stakePosition(new anchor.BN(1000), {
+      accounts: {
+        staker: dave.publicKey,
+        currentStakingPool: davePda.stake_pool,
+        poolAction: davePda.pool_action,
+        lotPosition: lotPositionPda,
+        tokenInterest: await getTokenInterestPDA(mintAddress),
+        stakingVaultAssociatedAddress: stakingVaultAssociatedAddress,
+        stakerAssociatedAddress: daveTokenAccount,
+        tokenMint: mintAddress,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [dave],
+    });
+
+    try {
+      await program.rpc.closePosition({
+        accounts: {
+          staker: dave.publicKey,
+          currentStakingPool: davePda.stake_pool,
+          poolAction: davePda.pool_action,
+          lockPoolAction: davePda.lock_pool,
+          withdrawPoolAction: davePda.withdraw_pool,
+          lotPosition: lotPositionPda,
+          tokenMint: mintAddress,
+        },
+        signers: [dave],
+      });
+      assert.fail('position with zero-copy lots was closed');
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, 'PositionNotEmpty');
+    }
+
+    let lockPoolInfo = await provider.connection.getAccountInfo(
+      davePda.lock_pool
+    );
+    assert.isNotNull(lockPoolInfo);
   });
 
   it('Get Deposit/Withdraw Entry Data', async () => {